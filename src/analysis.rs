@@ -0,0 +1,323 @@
+//! Checks everything a message reveals about its origin: the relays in its `Received` header
+//! fields, the HELO names they were given, the envelope sender, `From` and `Reply-To` addresses
+//! and the hosts of all URLs in its text and HTML parts.
+//!
+//! Reports serialize to JSON and render as Markdown with [`Report::to_markdown`].
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use futures::future;
+use mailparse::{addrparse, DispositionType, MailAddr, MailHeaderMap, MailParseError, ParsedMail};
+use serde::{Deserialize, Serialize};
+
+use crate::received::{HopReport, HopStatus, ReceivedConfig};
+use crate::rhsbl::{email_domain, normalize_domain};
+use crate::uri::{Host, HostResult};
+use crate::{BlockList, BlockStatus, CheckResults, DNSBL};
+
+/// Header fields whose addresses are checked, the envelope sender first.
+const SENDER_FIELDS: [&str; 3] = ["Return-Path", "From", "Reply-To"];
+
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisConfig {
+    /// Lists for relay addresses and IP hosts in URLs.
+    pub ip_lists: Vec<BlockList>,
+    /// Lists for sender domains, HELO names and URL hosts.
+    pub domain_lists: Vec<BlockList>,
+    /// Lists for hashed sender addresses, see [`DNSBL::check_hash`].
+    pub hash_lists: Vec<BlockList>,
+    pub received: ReceivedConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderResult {
+    /// Header field the address was taken from.
+    pub field: String,
+    pub address: String,
+    /// Results of the address' domain on the domain lists.
+    pub domain: CheckResults,
+    /// Results of the address on the hash lists.
+    pub hash: CheckResults,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+    pub relays: Vec<HopReport>,
+    pub helo: Vec<HostResult>,
+    pub senders: Vec<SenderResult>,
+    pub urls: Vec<HostResult>,
+}
+
+impl Report {
+    /// Whether any list reports anything about the message.
+    pub fn is_listed(&self) -> bool {
+        self.results().any(CheckResults::is_blocked)
+    }
+
+    /// Whether any list could not be queried.
+    pub fn has_errors(&self) -> bool {
+        self.results()
+            .flatten()
+            .any(|result| matches!(result.status, BlockStatus::Error { .. }))
+    }
+
+    fn results(&self) -> impl Iterator<Item = &CheckResults> {
+        let relays = self.relays.iter().filter_map(|relay| match &relay.status {
+            HopStatus::Checked(results) => Some(results),
+            _ => None,
+        });
+        let senders = self
+            .senders
+            .iter()
+            .flat_map(|sender| vec![&sender.domain, &sender.hash]);
+        let hosts = self.helo.iter().chain(&self.urls).map(|host| &host.results);
+        relays.chain(senders).chain(hosts)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        let subject = self.subject.as_deref().unwrap_or("(no subject)");
+        let _ = writeln!(markdown, "# {}\n", escape(subject));
+        if let Some(message_id) = &self.message_id {
+            let _ = writeln!(markdown, "Message-ID: `{}`\n", message_id);
+        }
+
+        let _ = writeln!(markdown, "## Relays\n");
+        let _ = writeln!(markdown, "| Hop | Client | HELO | Result |");
+        let _ = writeln!(markdown, "|---|---|---|---|");
+        for relay in &self.relays {
+            let client = relay.hop.ip.map(|ip| ip.to_string()).unwrap_or_default();
+            let helo = relay.hop.helo.as_deref().unwrap_or_default();
+            let result = match &relay.status {
+                HopStatus::NoAddress => "no client address".to_owned(),
+                HopStatus::Private => "private, not checked".to_owned(),
+                HopStatus::Trusted => "trusted, not checked".to_owned(),
+                HopStatus::Checked(results) => summary(results),
+            };
+            let _ = writeln!(
+                markdown,
+                "| {} | {} | {} | {} |",
+                relay.hop.index,
+                client,
+                escape(helo),
+                result
+            );
+        }
+
+        let _ = writeln!(markdown, "\n## Senders\n");
+        let _ = writeln!(markdown, "| Field | Address | Domain | Hash |");
+        let _ = writeln!(markdown, "|---|---|---|---|");
+        for sender in &self.senders {
+            let _ = writeln!(
+                markdown,
+                "| {} | {} | {} | {} |",
+                sender.field,
+                escape(&sender.address),
+                summary(&sender.domain),
+                summary(&sender.hash)
+            );
+        }
+
+        for (title, hosts) in [("HELO names", &self.helo), ("URLs", &self.urls)] {
+            let _ = writeln!(markdown, "\n## {}\n", title);
+            let _ = writeln!(markdown, "| Host | Result |");
+            let _ = writeln!(markdown, "|---|---|");
+            for host in hosts {
+                let _ = writeln!(markdown, "| {} | {} |", host.host, summary(&host.results));
+            }
+        }
+
+        markdown
+    }
+}
+
+/// Splits an mbox file into its messages, undoing the `>From ` quoting.
+pub fn split_mbox(mbox: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+
+    for line in mbox.split_inclusive(|&byte| byte == b'\n') {
+        if line.starts_with(b"From ") {
+            messages.extend(current.replace(Vec::new()));
+            continue;
+        }
+        if let Some(message) = &mut current {
+            let quoted = line.iter().take_while(|&&byte| byte == b'>').count();
+            if quoted > 0 && line[quoted..].starts_with(b"From ") {
+                message.extend_from_slice(&line[1..]);
+            } else {
+                message.extend_from_slice(line);
+            }
+        }
+    }
+    messages.extend(current);
+    messages
+}
+
+/// Every `text/plain` and `text/html` part that is not an attachment, decoded.
+fn text(mail: &ParsedMail) -> String {
+    if !mail.subparts.is_empty() {
+        return mail
+            .subparts
+            .iter()
+            .map(text)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    let is_text = matches!(mail.ctype.mimetype.as_str(), "text/plain" | "text/html");
+    let is_attachment = mail.get_content_disposition().disposition == DispositionType::Attachment;
+    if is_text && !is_attachment {
+        mail.get_body().unwrap_or_default()
+    } else {
+        String::new()
+    }
+}
+
+fn sender_addresses(mail: &ParsedMail) -> Vec<(String, String)> {
+    let mut addresses = Vec::new();
+    for field in SENDER_FIELDS {
+        for value in mail.headers.get_all_values(field) {
+            let parsed = match addrparse(&value) {
+                Ok(parsed) => parsed,
+                Err(_) => continue,
+            };
+            for address in parsed.iter() {
+                let singles = match address {
+                    MailAddr::Single(single) => vec![single],
+                    MailAddr::Group(group) => group.addrs.iter().collect(),
+                };
+                addresses.extend(
+                    singles
+                        .into_iter()
+                        .filter(|single| email_domain(&single.addr).is_some())
+                        .map(|single| (field.to_owned(), single.addr.clone())),
+                );
+            }
+        }
+    }
+    addresses.dedup();
+    addresses
+}
+
+impl DNSBL {
+    /// Checks one RFC 5322 message.
+    pub async fn analyze_message(
+        &self,
+        config: &AnalysisConfig,
+        message: &[u8],
+    ) -> Result<Report, MailParseError> {
+        let mail = mailparse::parse_mail(message)?;
+        let raw = String::from_utf8_lossy(message);
+        let headers = mail.get_headers();
+
+        let relays = self.check_received(&config.ip_lists, &raw, &config.received);
+        let body = text(&mail);
+        let urls = self.check_text(&config.domain_lists, &config.ip_lists, &body);
+        let senders = future::join_all(sender_addresses(&mail).into_iter().map(
+            |(field, address)| async move {
+                let domain = match email_domain(&address).map(normalize_domain) {
+                    Some(Ok(domain)) => self.check_rhsbl_all(&config.domain_lists, &domain).await,
+                    _ => CheckResults(Vec::new()),
+                };
+                let hash = self.check_hash_all(&config.hash_lists, &address).await;
+                SenderResult {
+                    field,
+                    address,
+                    domain,
+                    hash,
+                }
+            },
+        ));
+        let (relays, urls, senders) = future::join3(relays, urls, senders).await;
+
+        // HELO names of relays that were checked, the others are under our own control.
+        let mut seen = HashSet::new();
+        let helo_names: Vec<Host> = relays
+            .iter()
+            .filter(|relay| matches!(relay.status, HopStatus::Checked(_)))
+            .filter_map(|relay| relay.hop.helo.as_deref())
+            .filter(|helo| helo.contains('.'))
+            .filter_map(|helo| normalize_domain(helo).ok())
+            .map(Host::Domain)
+            .filter(|host| seen.insert(host.clone()))
+            .collect();
+        let helo = future::join_all(helo_names.into_iter().map(|host| async move {
+            let results = self.check_host(&config.domain_lists, &[], &host).await;
+            HostResult { host, results }
+        }))
+        .await;
+
+        Ok(Report {
+            message_id: headers.get_first_value("Message-ID"),
+            subject: headers.get_first_value("Subject"),
+            relays,
+            helo,
+            senders,
+            urls,
+        })
+    }
+
+    /// Checks a single message or, if `contents` starts with a `From ` line, every message of
+    /// an mbox file.
+    pub async fn analyze_mailbox(
+        &self,
+        config: &AnalysisConfig,
+        contents: &[u8],
+    ) -> Result<Vec<Report>, MailParseError> {
+        let messages = if contents.starts_with(b"From ") {
+            split_mbox(contents)
+        } else {
+            vec![contents.to_vec()]
+        };
+
+        let mut reports = Vec::with_capacity(messages.len());
+        for message in messages {
+            reports.push(self.analyze_message(config, &message).await?);
+        }
+        Ok(reports)
+    }
+}
+
+/// One line summary of `results` for the Markdown tables.
+fn summary(results: &CheckResults) -> String {
+    if results.is_empty() {
+        return "not checked".to_owned();
+    }
+
+    let listed: Vec<String> = results
+        .iter()
+        .filter_map(|result| match &result.status {
+            BlockStatus::Blocked { reasons, .. } if reasons.is_empty() => {
+                Some(format!("**{}**", result.list))
+            }
+            BlockStatus::Blocked { reasons, .. } => Some(format!(
+                "**{}** ({})",
+                result.list,
+                reasons.iter().cloned().collect::<Vec<_>>().join(", ")
+            )),
+            _ => None,
+        })
+        .collect();
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|result| matches!(result.status, BlockStatus::Error { .. }))
+        .map(|result| result.list.to_string())
+        .collect();
+
+    let mut summary = if listed.is_empty() {
+        "not listed".to_owned()
+    } else {
+        format!("listed by {}", listed.join(", "))
+    };
+    if !failed.is_empty() {
+        let _ = write!(summary, ", failed: {}", failed.join(", "));
+    }
+    summary
+}
+
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
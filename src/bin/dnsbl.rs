@@ -0,0 +1,735 @@
+use std::net::IpAddr;
+#[cfg(any(feature = "server", feature = "analysis"))]
+use std::path::PathBuf;
+use std::process::ExitCode;
+#[cfg(feature = "server")]
+use std::str::FromStr;
+use std::time::Duration;
+#[cfg(feature = "analysis")]
+use std::{io::Read, path::Path};
+#[cfg(any(feature = "server", feature = "http"))]
+use std::{net::SocketAddr, sync::Arc};
+
+use clap::{Args, Parser, Subcommand};
+
+#[cfg(feature = "analysis")]
+use dnsbl::analysis::{AnalysisConfig, Report};
+#[cfg(feature = "http")]
+use dnsbl::http::HttpConfig;
+#[cfg(feature = "policy")]
+use dnsbl::policy::{Action, PolicyConfig, PolicyServer};
+#[cfg(feature = "server")]
+use dnsbl::{proxy::ProxyZone, rbldnsd::DatasetKind, server::Server};
+#[cfg(feature = "analysis")]
+use dnsbl::{received::ReceivedConfig, Network};
+use dnsbl::{
+    AddressPolicy, BlockList, BlockStatus, CacheConfig, CheckResults, Domain, QueryKind, DNSBL,
+};
+
+/// Check IP addresses and domains against DNS blocklists.
+///
+/// Exits with 0 if the target is not listed, 1 if any list reports it, 3 if a list could not be
+/// queried and 4 if no list was queried for the target. Invalid arguments exit with 2.
+#[derive(Parser)]
+#[command(name = "dnsbl", version)]
+struct Cli {
+    #[command(flatten)]
+    resolver: ResolverArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ResolverArgs {
+    /// Use the system resolver configuration instead of Cloudflare over TLS
+    #[arg(long, global = true)]
+    system: bool,
+    /// Plain DNS name server to query, can be given multiple times
+    #[arg(long = "nameserver", value_name = "IP", global = true)]
+    name_servers: Vec<IpAddr>,
+    /// Timeout of a single DNS query
+    #[arg(long, value_name = "SECONDS", global = true)]
+    timeout: Option<u64>,
+    /// Also query private, loopback and other special purpose addresses
+    #[arg(long, global = true)]
+    query_all: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check an IP address or domain against one or more lists
+    Check {
+        /// IP address or domain to check
+        target: String,
+        /// List to query, can be given multiple times
+        #[arg(short, long = "list", value_name = "LIST", value_parser = well_known_list, required = true)]
+        lists: Vec<BlockList>,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run a Postfix policy delegation server
+    #[cfg(feature = "policy")]
+    Policy {
+        /// Address to listen on, `inet:HOST:PORT` or `unix:PATH`
+        #[arg(long, value_name = "ADDRESS", default_value = "inet:127.0.0.1:10040")]
+        listen: String,
+        /// List for the client address, can be given multiple times
+        #[arg(long = "client-list", value_name = "LIST", value_parser = well_known_list)]
+        client_lists: Vec<BlockList>,
+        /// List for the HELO domain, can be given multiple times
+        #[arg(long = "helo-list", value_name = "LIST", value_parser = well_known_list)]
+        helo_lists: Vec<BlockList>,
+        /// List for the sender domain, can be given multiple times
+        #[arg(long = "sender-list", value_name = "LIST", value_parser = well_known_list)]
+        sender_lists: Vec<BlockList>,
+        /// Action if the client is listed: reject, defer or dunno
+        #[arg(long, value_name = "ACTION", default_value = "reject")]
+        on_listed: Action,
+        /// Action if a list could not be queried: reject, defer or dunno
+        #[arg(long, value_name = "ACTION", default_value = "dunno")]
+        on_error: Action,
+    },
+    /// Serve rbldnsd data files and aggregated upstream lists as DNSBL zones over UDP and TCP
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:5353")]
+        listen: SocketAddr,
+        /// Zone to serve as `ZONE:TYPE:FILE[,FILE...]`, can be given multiple times
+        #[arg(long = "zone", value_name = "SPEC")]
+        zones: Vec<ZoneSpec>,
+        /// Proxy zone aggregating upstream lists as `ZONE:LIST[,LIST...]`, can be given
+        /// multiple times
+        #[arg(long = "proxy", value_name = "SPEC")]
+        proxies: Vec<ProxySpec>,
+        /// TTL of proxy zone answers
+        #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+        proxy_ttl: u32,
+        /// Seconds between checks for changed data files, 0 disables reloading
+        #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+        reload: u64,
+        /// Maximum number of UDP queries and TCP connections handled at the same time
+        #[arg(long, value_name = "COUNT", default_value_t = 1024)]
+        max_pending: usize,
+    },
+    /// Serve a JSON API for checks over HTTP
+    #[cfg(feature = "http")]
+    Http {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// List to query, can be given multiple times
+        #[arg(short, long = "list", value_name = "LIST", value_parser = well_known_list, required = true)]
+        lists: Vec<BlockList>,
+        /// Upper bound for the time spent on one request
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        request_timeout: u64,
+        /// Maximum number of targets in a bulk request
+        #[arg(long, value_name = "COUNT", default_value_t = 100)]
+        max_targets: usize,
+        /// Seconds /healthz reuses its last health checks, 0 checks on every request
+        #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+        health_interval: u64,
+    },
+    /// Check the relays, senders, HELO names and URLs of an .eml or mbox file
+    #[cfg(feature = "analysis")]
+    Analyze {
+        /// Message or mbox file, `-` reads standard input
+        file: PathBuf,
+        /// List for relay addresses and IP hosts in URLs, can be given multiple times
+        #[arg(long = "ip-list", value_name = "LIST", value_parser = well_known_list)]
+        ip_lists: Vec<BlockList>,
+        /// List for sender domains, HELO names and URL hosts, can be given multiple times
+        #[arg(long = "domain-list", value_name = "LIST", value_parser = well_known_list)]
+        domain_lists: Vec<BlockList>,
+        /// List for hashed sender addresses, can be given multiple times
+        #[arg(long = "hash-list", value_name = "LIST", value_parser = well_known_list)]
+        hash_lists: Vec<BlockList>,
+        /// Relay that is not checked as `ADDRESS[/PREFIX]`, can be given multiple times
+        #[arg(long = "trusted", value_name = "NETWORK")]
+        trusted: Vec<Network>,
+        /// Print the reports as JSON instead of Markdown
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// `ZONE:TYPE:FILE[,FILE...]` as accepted by rbldnsd.
+#[cfg(feature = "server")]
+#[derive(Clone)]
+struct ZoneSpec {
+    origin: Domain,
+    kind: DatasetKind,
+    files: Vec<PathBuf>,
+}
+
+#[cfg(feature = "server")]
+impl FromStr for ZoneSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(origin), Some(kind), Some(files)) => Ok(Self {
+                origin: Domain::new(origin).map_err(|error| error.to_string())?,
+                kind: kind.parse()?,
+                files: files.split(',').map(PathBuf::from).collect(),
+            }),
+            _ => Err(format!("expected ZONE:TYPE:FILE, got {:?}", s)),
+        }
+    }
+}
+
+/// `ZONE:LIST[,LIST...]`
+#[cfg(feature = "server")]
+#[derive(Clone)]
+struct ProxySpec {
+    origin: Domain,
+    lists: Vec<BlockList>,
+}
+
+#[cfg(feature = "server")]
+impl FromStr for ProxySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, lists) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected ZONE:LIST, got {:?}", s))?;
+        let lists = lists
+            .split(',')
+            .map(well_known_list)
+            .collect::<Result<Vec<_>, _>>()?;
+        if lists.len() > ProxyZone::MAX_LISTS {
+            return Err(format!(
+                "a proxy zone supports at most {} lists",
+                ProxyZone::MAX_LISTS
+            ));
+        }
+
+        Ok(Self {
+            origin: Domain::new(origin).map_err(|error| error.to_string())?,
+            lists,
+        })
+    }
+}
+
+const EXIT_NOT_LISTED: u8 = 0;
+const EXIT_LISTED: u8 = 1;
+/// Distinct from the code 2 clap exits with on usage errors.
+const EXIT_ERROR: u8 = 3;
+/// Every list was skipped, because of the address policy or the kinds the lists support.
+const EXIT_NOT_APPLICABLE: u8 = 4;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let dnsbl = match build_dnsbl(&cli.resolver) {
+        Ok(dnsbl) => dnsbl,
+        Err(error) => {
+            eprintln!("dnsbl: failed to create resolver: {}", error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    match cli.command {
+        Command::Check {
+            target,
+            lists,
+            json,
+        } => check(&dnsbl, &target, &lists, json).await,
+        #[cfg(feature = "policy")]
+        Command::Policy {
+            listen,
+            client_lists,
+            helo_lists,
+            sender_lists,
+            on_listed,
+            on_error,
+        } => {
+            let config = PolicyConfig {
+                client_lists,
+                helo_lists,
+                sender_lists,
+                on_listed,
+                on_error,
+            };
+            policy(PolicyServer::new(dnsbl, config), &listen).await
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            listen,
+            zones,
+            proxies,
+            proxy_ttl,
+            reload,
+            max_pending,
+        } => {
+            let server = Server::new().max_pending(max_pending);
+            let dnsbl = Arc::new(dnsbl);
+            for proxy in proxies {
+                let zone = ProxyZone::new(dnsbl.clone(), proxy.lists);
+                server.set_proxy_zone(&proxy.origin, zone, proxy_ttl);
+            }
+            serve(server, listen, zones, reload).await
+        }
+        #[cfg(feature = "http")]
+        Command::Http {
+            listen,
+            lists,
+            request_timeout,
+            max_targets,
+            health_interval,
+        } => {
+            let config = HttpConfig {
+                lists,
+                timeout: Duration::from_secs(request_timeout),
+                max_targets,
+                health_interval: Duration::from_secs(health_interval),
+            };
+            if let Err(error) = dnsbl::http::serve(listen, Arc::new(dnsbl), config).await {
+                eprintln!("dnsbl: http server failed: {}", error);
+            }
+            ExitCode::from(EXIT_ERROR)
+        }
+        #[cfg(feature = "analysis")]
+        Command::Analyze {
+            file,
+            ip_lists,
+            domain_lists,
+            hash_lists,
+            trusted,
+            json,
+        } => {
+            let config = AnalysisConfig {
+                ip_lists,
+                domain_lists,
+                hash_lists,
+                received: ReceivedConfig {
+                    trusted,
+                    ..ReceivedConfig::default()
+                },
+            };
+            analyze(&dnsbl, &file, &config, json).await
+        }
+    }
+}
+
+async fn check(dnsbl: &DNSBL, target: &str, lists: &[BlockList], json: bool) -> ExitCode {
+    let (results, kind) = match target.parse::<IpAddr>() {
+        Ok(ip) => {
+            let kind = match dnsbl.address_policy().normalize(ip) {
+                IpAddr::V4(_) => QueryKind::Ipv4,
+                IpAddr::V6(_) => QueryKind::Ipv6,
+            };
+            (dnsbl.check_ip_all(lists, ip).await, kind)
+        }
+        Err(_) => match Domain::new(target) {
+            Ok(domain) => (
+                dnsbl.check_domain_all(lists, &domain).await,
+                QueryKind::Domain,
+            ),
+            Err(error) => {
+                eprintln!("dnsbl: invalid target {:?}: {}", target, error);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        },
+    };
+
+    if json {
+        print_json(&results);
+    } else {
+        print_table(&results, kind);
+    }
+
+    ExitCode::from(exit_code(&results))
+}
+
+#[cfg(feature = "policy")]
+async fn policy(server: PolicyServer, listen: &str) -> ExitCode {
+    let result = match listen.split_once(':') {
+        Some(("inet", address)) => match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => server.serve_tcp(listener).await,
+            Err(error) => Err(error),
+        },
+        #[cfg(unix)]
+        Some(("unix", path)) => match tokio::net::UnixListener::bind(path) {
+            Ok(listener) => server.serve_unix(listener).await,
+            Err(error) => Err(error),
+        },
+        _ => {
+            eprintln!("dnsbl: invalid listen address {:?}", listen);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("dnsbl: policy server failed: {}", error);
+    }
+    ExitCode::from(EXIT_ERROR)
+}
+
+#[cfg(feature = "server")]
+async fn serve(server: Server, listen: SocketAddr, specs: Vec<ZoneSpec>, reload: u64) -> ExitCode {
+    let mut zones: Vec<(Domain, Vec<(DatasetKind, PathBuf)>)> = Vec::new();
+    for spec in specs {
+        let kind = spec.kind;
+        let files = spec.files.into_iter().map(move |file| (kind, file));
+        match zones.iter_mut().find(|(origin, _)| *origin == spec.origin) {
+            Some((_, existing)) => existing.extend(files),
+            None => zones.push((spec.origin.clone(), files.collect())),
+        }
+    }
+
+    for (origin, files) in zones {
+        if let Err(error) = server.load_zone(&origin, files) {
+            eprintln!("dnsbl: failed to load zone {}: {}", origin, error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+
+    let sockets = tokio::try_join!(
+        tokio::net::UdpSocket::bind(listen),
+        tokio::net::TcpListener::bind(listen)
+    );
+    let (udp, tcp) = match sockets {
+        Ok(sockets) => sockets,
+        Err(error) => {
+            eprintln!("dnsbl: failed to listen on {}: {}", listen, error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    if reload > 0 {
+        let watcher = server.clone();
+        tokio::spawn(async move {
+            watcher
+                .watch(Duration::from_secs(reload), |origin, error| {
+                    eprintln!("dnsbl: failed to reload zone {}: {}", origin, error)
+                })
+                .await
+        });
+    }
+
+    if let Err(error) = server.serve(udp, tcp).await {
+        eprintln!("dnsbl: server failed: {}", error);
+    }
+    ExitCode::from(EXIT_ERROR)
+}
+
+#[cfg(feature = "analysis")]
+async fn analyze(dnsbl: &DNSBL, file: &Path, config: &AnalysisConfig, json: bool) -> ExitCode {
+    let contents = if file == Path::new("-") {
+        let mut contents = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut contents)
+            .map(|_| contents)
+    } else {
+        std::fs::read(file)
+    };
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("dnsbl: failed to read {}: {}", file.display(), error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    let reports = match dnsbl.analyze_mailbox(config, &contents).await {
+        Ok(reports) => reports,
+        Err(error) => {
+            eprintln!("dnsbl: failed to parse {}: {}", file.display(), error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&reports).expect("reports are always serializable")
+        );
+    } else {
+        let markdown: Vec<String> = reports.iter().map(Report::to_markdown).collect();
+        print!("{}", markdown.join("\n"));
+    }
+
+    if reports.iter().any(Report::is_listed) {
+        ExitCode::from(EXIT_LISTED)
+    } else if reports.iter().any(Report::has_errors) {
+        ExitCode::from(EXIT_ERROR)
+    } else {
+        ExitCode::from(EXIT_NOT_LISTED)
+    }
+}
+
+fn build_dnsbl(args: &ResolverArgs) -> Result<DNSBL, dnsbl::Error> {
+    let mut builder = DNSBL::builder().cache(CacheConfig::default());
+    if args.system {
+        builder = builder.system_conf();
+    }
+    if !args.name_servers.is_empty() {
+        builder = builder.name_servers(&args.name_servers, 53);
+    }
+    if let Some(timeout) = args.timeout {
+        builder = builder.timeout(Duration::from_secs(timeout));
+    }
+    if args.query_all {
+        builder = builder.address_policy(AddressPolicy::query_all());
+    }
+    builder.build()
+}
+
+/// Parses a list and adds what is known about well-known lists.
+fn well_known_list(s: &str) -> Result<BlockList, String> {
+    let list: BlockList = s.parse().map_err(|error: dnsbl::Error| error.to_string())?;
+    let known = match list.to_string().trim_end_matches('.') {
+        "zen.spamhaus.org" => BlockList::spamhaus_zen(),
+        "dbl.spamhaus.org" => BlockList::spamhaus_dbl(),
+        "multi.surbl.org" => BlockList::surbl_multi(),
+        "multi.uribl.com" => BlockList::uribl_multi(),
+        "ebl.msbl.org" => BlockList::msbl_ebl(),
+        "hbl.dq.spamhaus.net" => {
+            return Err(
+                "Spamhaus HBL needs a Data Query Service key: KEY.hbl.dq.spamhaus.net".into(),
+            )
+        }
+        name => match name.strip_suffix(".hbl.dq.spamhaus.net") {
+            Some(key) => BlockList::spamhaus_hbl(key).map_err(|error| error.to_string())?,
+            None => return Ok(list),
+        },
+    };
+    Ok(BlockList {
+        domain: list.domain,
+        ..known
+    })
+}
+
+fn exit_code(results: &CheckResults) -> u8 {
+    if results.is_blocked() {
+        EXIT_LISTED
+    } else if results
+        .iter()
+        .any(|result| matches!(result.status, BlockStatus::Error { .. }))
+    {
+        EXIT_ERROR
+    } else if !results.is_empty()
+        && results
+            .iter()
+            .all(|result| result.status == BlockStatus::NotApplicable)
+    {
+        EXIT_NOT_APPLICABLE
+    } else {
+        EXIT_NOT_LISTED
+    }
+}
+
+fn print_table(results: &CheckResults, kind: QueryKind) {
+    let header = ["LIST", "STATUS", "CODES", "REASONS", "TIME", "MESSAGE"].map(String::from);
+    let rows: Vec<[String; 6]> = results
+        .iter()
+        .map(|result| {
+            let (status, codes, reasons, message) = match &result.status {
+                BlockStatus::Blocked {
+                    addresses,
+                    reasons,
+                    message,
+                } => (
+                    "listed".to_owned(),
+                    join(addresses),
+                    join(reasons),
+                    message.clone().unwrap_or_default(),
+                ),
+                BlockStatus::NotBlocked => (
+                    "not listed".to_owned(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ),
+                BlockStatus::NotApplicable => (
+                    "not applicable".to_owned(),
+                    String::new(),
+                    String::new(),
+                    if result.list.supports(kind) {
+                        "address skipped by policy".to_owned()
+                    } else {
+                        format!("list has no {} data", kind_name(kind))
+                    },
+                ),
+                BlockStatus::Error { kind, source } => (
+                    format!("error ({:?})", kind),
+                    String::new(),
+                    String::new(),
+                    source.to_string(),
+                ),
+            };
+            [
+                result.list.to_string(),
+                status,
+                codes,
+                reasons,
+                format!("{}ms", result.elapsed.as_millis()),
+                message,
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+fn print_json(results: &CheckResults) {
+    println!(
+        "{}",
+        serde_json::to_string(results).expect("results are always serializable")
+    );
+}
+
+fn kind_name(kind: QueryKind) -> &'static str {
+    match kind {
+        QueryKind::Ipv4 => "IPv4",
+        QueryKind::Ipv6 => "IPv6",
+        QueryKind::Domain => "domain",
+        QueryKind::Hash => "hash",
+    }
+}
+
+fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use dnsbl::{MemoryLookup, Name};
+
+    use super::*;
+
+    fn list(name: &str) -> BlockList {
+        name.parse().unwrap()
+    }
+
+    /// A name with a label too long for DNS.
+    fn invalid_name() -> String {
+        format!("{}.example", "a".repeat(64))
+    }
+
+    fn dnsbl() -> DNSBL {
+        let lookup = MemoryLookup::new()
+            .list_ip(
+                &list("listed.test"),
+                Ipv4Addr::new(1, 2, 3, 4),
+                Ipv4Addr::new(127, 0, 0, 2),
+                None,
+            )
+            .error(
+                Name::from_ascii("4.3.2.1.broken.test.").unwrap(),
+                dnsbl::Error::from("broken".to_owned()),
+            );
+        DNSBL::with_lookup(lookup)
+    }
+
+    #[tokio::test]
+    async fn exit_codes() {
+        let dnsbl = dnsbl();
+        let ip = Ipv4Addr::new(1, 2, 3, 4);
+        let exit = |results: CheckResults| exit_code(&results);
+
+        let lists = [list("listed.test"), list("broken.test")];
+        assert_eq!(exit(dnsbl.check_ip_all(&lists, ip).await), EXIT_LISTED);
+        let lists = [list("clean.test"), list("broken.test")];
+        assert_eq!(exit(dnsbl.check_ip_all(&lists, ip).await), EXIT_ERROR);
+        let lists = [list("clean.test")];
+        assert_eq!(exit(dnsbl.check_ip_all(&lists, ip).await), EXIT_NOT_LISTED);
+
+        // skipped by the address policy
+        let private = Ipv4Addr::new(10, 0, 0, 1);
+        assert_eq!(
+            exit(dnsbl.check_ip_all(&lists, private).await),
+            EXIT_NOT_APPLICABLE
+        );
+        // skipped by every list, but not by some of them
+        let domain = Domain::new("example.com").unwrap();
+        let lists = [BlockList::spamhaus_zen()];
+        assert_eq!(
+            exit(dnsbl.check_domain_all(&lists, &domain).await),
+            EXIT_NOT_APPLICABLE
+        );
+        let lists = [BlockList::spamhaus_zen(), list("clean.test")];
+        assert_eq!(
+            exit(dnsbl.check_domain_all(&lists, &domain).await),
+            EXIT_NOT_LISTED
+        );
+    }
+
+    #[test]
+    fn well_known_lists() {
+        let zen = well_known_list("zen.spamhaus.org.").unwrap();
+        assert_eq!(zen.domain, Domain::new("zen.spamhaus.org.").unwrap());
+        assert!(!zen.supports(QueryKind::Domain));
+        assert!(zen.description.is_some());
+
+        let hbl = well_known_list("key.hbl.dq.spamhaus.net").unwrap();
+        assert_eq!(hbl.domain, Domain::new("key.hbl.dq.spamhaus.net").unwrap());
+        assert!(hbl.supports(QueryKind::Hash));
+        assert!(hbl.hash_descriptor.is_some());
+        assert!(well_known_list("hbl.dq.spamhaus.net").is_err());
+        assert!(well_known_list("a.b.hbl.dq.spamhaus.net").is_err());
+
+        let other = well_known_list("bl.example").unwrap();
+        assert!(QueryKind::ALL.iter().all(|&kind| other.supports(kind)));
+        assert!(other.description.is_none());
+
+        assert!(well_known_list(&invalid_name()).is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn zone_specs() {
+        let spec: ZoneSpec = "bl.example:ip4set:a.txt,b.txt".parse().unwrap();
+        assert_eq!(spec.origin, Domain::new("bl.example").unwrap());
+        assert_eq!(spec.kind, DatasetKind::Ip4Set);
+        assert_eq!(spec.files, [PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+
+        assert!("bl.example:ip4set".parse::<ZoneSpec>().is_err());
+        assert!("bl.example:unknown:a.txt".parse::<ZoneSpec>().is_err());
+        let spec = format!("{}:ip4set:a.txt", invalid_name());
+        assert!(spec.parse::<ZoneSpec>().is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn proxy_specs() {
+        let spec: ProxySpec = "proxy.example:zen.spamhaus.org,bl.example".parse().unwrap();
+        assert_eq!(spec.origin, Domain::new("proxy.example").unwrap());
+        assert_eq!(spec.lists, [list("zen.spamhaus.org"), list("bl.example")]);
+        assert!(spec.lists[0].description.is_some());
+
+        assert!("proxy.example".parse::<ProxySpec>().is_err());
+        let spec = format!("proxy.example:{}", invalid_name());
+        assert!(spec.parse::<ProxySpec>().is_err());
+        let lists = ["bl.example"; ProxyZone::MAX_LISTS + 1].join(",");
+        let spec = format!("proxy.example:{}", lists);
+        assert!(spec.parse::<ProxySpec>().is_err());
+    }
+}
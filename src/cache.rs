@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use trust_dns_resolver::Name;
+
+use crate::{BlockStatus, Domain};
+
+/// Bounds for the in-process result cache of [`DNSBL`](crate::DNSBL).
+///
+/// Listed answers are cached for their TTL clamped to `min_ttl..=max_ttl`. Not-listed answers
+/// use the negative TTL from the list's SOA record, falling back to `negative_ttl` and never
+/// exceeding it. Lookup failures are never cached.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub capacity: usize,
+    pub min_ttl: Duration,
+    pub max_ttl: Duration,
+    pub negative_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            min_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(3600),
+            negative_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Map of expiring entries holding at most `capacity` entries. When full, inserting drops the
+/// entry that expires first, which is an expired one if there is any.
+pub(crate) struct ExpiryMap<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, (Instant, u64))>,
+    /// Keys ordered by expiry, the counter tells entries expiring at the same instant apart.
+    expiries: BTreeMap<(Instant, u64), K>,
+    counter: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> ExpiryMap<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            expiries: BTreeMap::new(),
+            counter: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &K) -> Option<V> {
+        let (value, expiry) = self.entries.get(key)?;
+        if expiry.0 > Instant::now() {
+            return Some(value.clone());
+        }
+        self.remove(key);
+        None
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V, expires: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.expiries.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        let expiry = (expires, self.counter);
+        self.counter += 1;
+        self.expiries.insert(expiry, key.clone());
+        self.entries.insert(key, (value, expiry));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, expiry)) = self.entries.remove(key) {
+            self.expiries.remove(&expiry);
+        }
+    }
+}
+
+pub(crate) struct Cache {
+    config: CacheConfig,
+    entries: Mutex<ExpiryMap<(Domain, Name), BlockStatus>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(ExpiryMap::new(config.capacity)),
+            config,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn get(&self, list: &Domain, dns_name: &Name) -> Option<BlockStatus> {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        let key = (list.clone(), dns_name.clone());
+
+        let status = entries.get(&key);
+
+        let counter = if status.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        status
+    }
+
+    /// Caches `status` for `ttl` as reported by the answer, clamped by the configuration.
+    pub(crate) fn insert(
+        &self,
+        list: Domain,
+        dns_name: Name,
+        status: BlockStatus,
+        ttl: Option<Duration>,
+    ) {
+        let ttl = match status {
+            BlockStatus::Blocked { .. } => ttl
+                .unwrap_or(self.config.min_ttl)
+                .max(self.config.min_ttl)
+                .min(self.config.max_ttl),
+            BlockStatus::NotBlocked => ttl
+                .unwrap_or(self.config.negative_ttl)
+                .max(self.config.min_ttl)
+                .min(self.config.negative_ttl),
+            BlockStatus::NotApplicable | BlockStatus::Error { .. } => return,
+        };
+        if ttl.as_secs() == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        entries.insert((list, dns_name), status, Instant::now() + ttl);
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().expect("cache lock poisoned").len(),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::convert::TryFrom;
+
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{BlockList, BlockStatus, CheckResults, Error, Name, QueryKind, DNSBL};
+
+/// Longest label allowed in a DNS name.
+const MAX_LABEL: usize = 63;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashEncoding {
+    /// Lowercase hexadecimal, too long for SHA-256 digests.
+    Hex,
+    /// RFC 4648 base32 without padding.
+    Base32,
+}
+
+/// How a list expects values to be turned into query names: `<digest>[.<label>].<list>`.
+///
+/// Lists without a [`BlockList::hash_descriptor`] are queried with hex encoded SHA-1 digests of
+/// the unmodified value. Deserializes from an object, descriptors whose digests or label do not
+/// fit into a DNS name are rejected:
+///
+/// ```json
+/// {"algorithm": "sha1", "encoding": "base32", "lowercase": true, "label": "_email"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Repr")]
+pub struct HashDescriptor {
+    algorithm: HashAlgorithm,
+    encoding: HashEncoding,
+    lowercase: bool,
+    gmail_dots: bool,
+    strip_tag: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+static DEFAULT_DESCRIPTOR: HashDescriptor =
+    HashDescriptor::new(HashAlgorithm::Sha1, HashEncoding::Hex);
+
+impl Default for HashDescriptor {
+    fn default() -> Self {
+        DEFAULT_DESCRIPTOR.clone()
+    }
+}
+
+impl HashDescriptor {
+    pub const fn new(algorithm: HashAlgorithm, encoding: HashEncoding) -> Self {
+        Self {
+            algorithm,
+            encoding,
+            lowercase: false,
+            gmail_dots: false,
+            strip_tag: false,
+            label: None,
+        }
+    }
+
+    /// Lowercases and trims values before hashing.
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+
+    /// Removes the dots from the local part of Gmail addresses, which Gmail ignores.
+    pub fn gmail_dots(mut self) -> Self {
+        self.gmail_dots = true;
+        self
+    }
+
+    /// Removes `+tag` suffixes from the local part of email addresses.
+    pub fn strip_tag(mut self) -> Self {
+        self.strip_tag = true;
+        self
+    }
+
+    /// Label between the digest and the list, such as `_email`.
+    pub fn label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Email addresses on `hbl.dq.spamhaus.net`.
+    pub fn spamhaus_hbl_email() -> Self {
+        Self::new(HashAlgorithm::Sha1, HashEncoding::Base32)
+            .lowercase()
+            .label("_email")
+    }
+
+    /// `ebl.msbl.org`
+    pub fn msbl_ebl() -> Self {
+        Self::new(HashAlgorithm::Sha1, HashEncoding::Hex)
+            .lowercase()
+            .gmail_dots()
+            .strip_tag()
+    }
+
+    /// Applies the normalisation rules to `value`.
+    pub fn normalize(&self, value: &str) -> String {
+        let mut value = if self.lowercase {
+            value.trim().to_lowercase()
+        } else {
+            value.to_owned()
+        };
+
+        if let Some((local, domain)) = value.rsplit_once('@') {
+            let mut local = local.to_owned();
+            if self.strip_tag {
+                if let Some(tag) = local.find('+') {
+                    local.truncate(tag);
+                }
+            }
+            let is_gmail = ["gmail.com", "googlemail.com"]
+                .iter()
+                .any(|gmail| domain.eq_ignore_ascii_case(gmail));
+            if self.gmail_dots && is_gmail {
+                local.retain(|c| c != '.');
+            }
+            value = format!("{}@{}", local, domain);
+        }
+
+        value
+    }
+
+    /// The encoded digest of `value`, without any normalisation.
+    pub fn digest(&self, value: &[u8]) -> String {
+        let digest = match self.algorithm {
+            HashAlgorithm::Sha1 => Sha1::digest(value).to_vec(),
+            HashAlgorithm::Sha256 => Sha256::digest(value).to_vec(),
+        };
+        match self.encoding {
+            HashEncoding::Hex => HEXLOWER.encode(&digest),
+            HashEncoding::Base32 => BASE32_NOPAD.encode(&digest),
+        }
+    }
+
+    /// Rejects descriptors whose labels do not fit into a DNS name.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.digest(&[]).len() > MAX_LABEL {
+            return Err(Error::from(format!(
+                "{:?} digests encoded as {:?} do not fit into a DNS label",
+                self.algorithm, self.encoding
+            )));
+        }
+        match &self.label {
+            Some(label) if label.is_empty() || label.len() > MAX_LABEL => {
+                Err(Error::from(format!("invalid hash label {:?}", label)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn query(&self, list: &BlockList, value: &[u8]) -> Name {
+        let digest = self.digest(value);
+        let labels = std::iter::once(digest.as_bytes())
+            .chain(self.label.as_ref().map(String::as_bytes))
+            .chain(&list.domain.0);
+        Name::from_labels(labels).expect("always valid")
+    }
+}
+
+#[derive(Deserialize)]
+struct Repr {
+    algorithm: HashAlgorithm,
+    encoding: HashEncoding,
+    #[serde(default)]
+    lowercase: bool,
+    #[serde(default)]
+    gmail_dots: bool,
+    #[serde(default)]
+    strip_tag: bool,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+impl TryFrom<Repr> for HashDescriptor {
+    type Error = Error;
+
+    fn try_from(repr: Repr) -> Result<Self, Self::Error> {
+        let descriptor = Self {
+            algorithm: repr.algorithm,
+            encoding: repr.encoding,
+            lowercase: repr.lowercase,
+            gmail_dots: repr.gmail_dots,
+            strip_tag: repr.strip_tag,
+            label: repr.label,
+        };
+        descriptor.validate()?;
+        Ok(descriptor)
+    }
+}
+
+impl DNSBL {
+    /// Checks the digest of `value` after applying the normalisation rules of the list's
+    /// [`HashDescriptor`].
+    pub async fn check_hash(&self, list: &BlockList, value: &str) -> BlockStatus {
+        let value = descriptor_of(list).normalize(value);
+        self.check_hash_bytes(list, value.as_bytes()).await
+    }
+
+    /// Checks the digest of `value` as is, for example the contents of a file.
+    pub async fn check_hash_bytes(&self, list: &BlockList, value: &[u8]) -> BlockStatus {
+        if !list.supports(QueryKind::Hash) {
+            return BlockStatus::NotApplicable;
+        }
+        let dns_name = descriptor_of(list).query(list, value);
+        self.check(list, dns_name).await
+    }
+
+    /// Checks `value` against every list, see [`check_hash`](Self::check_hash).
+    pub async fn check_hash_all(&self, lists: &[BlockList], value: &str) -> CheckResults {
+        self.check_all(lists, QueryKind::Hash, |list| {
+            let descriptor = descriptor_of(list);
+            descriptor.query(list, descriptor.normalize(value).as_bytes())
+        })
+        .await
+    }
+}
+
+fn descriptor_of(list: &BlockList) -> &HashDescriptor {
+    list.hash_descriptor.as_ref().unwrap_or(&DEFAULT_DESCRIPTOR)
+}
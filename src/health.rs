@@ -0,0 +1,109 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::Name;
+
+use crate::{domain_query, ip_query, BlockList, BlockStatus, Domain, ErrorKind, QueryKind, DNSBL};
+
+/// State of one kind of query, derived from a pair of RFC 5782 test points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "health", content = "kind", rename_all = "snake_case")]
+pub enum Health {
+    /// The listed test point is listed and the unlisted one is not.
+    Alive,
+    /// The listed test point is not listed, the list is gone.
+    Dead,
+    /// The unlisted test point is listed, the list is wildcarded or answers everything.
+    ListsEverything,
+    /// A test point could not be queried.
+    Unreachable(ErrorKind),
+    /// The list does not support this kind of query, nothing was queried.
+    Unsupported,
+}
+
+impl Health {
+    fn from_test_points(listed: &BlockStatus, unlisted: &BlockStatus) -> Self {
+        match (listed, unlisted) {
+            (BlockStatus::Error { kind, .. }, _) | (_, BlockStatus::Error { kind, .. }) => {
+                Health::Unreachable(*kind)
+            }
+            (_, BlockStatus::Blocked { .. }) => Health::ListsEverything,
+            (BlockStatus::Blocked { .. }, _) => Health::Alive,
+            _ => Health::Dead,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    #[serde(with = "crate::list::list_name")]
+    pub list: BlockList,
+    /// `127.0.0.2` and `127.0.0.1`
+    pub ipv4: Health,
+    /// `::FFFF:7F00:2` and `::FFFF:7F00:1`
+    pub ipv6: Health,
+    /// `TEST` and `INVALID`
+    pub domain: Health,
+}
+
+impl HealthReport {
+    /// Whether the list works for at least one kind of query and lists nothing it should not.
+    pub fn is_alive(&self) -> bool {
+        let healths = [self.ipv4, self.ipv6, self.domain];
+        healths.contains(&Health::Alive) && !healths.contains(&Health::ListsEverything)
+    }
+}
+
+impl DNSBL {
+    /// Queries the RFC 5782 test points of `list`, bypassing the result cache. Kinds of queries
+    /// the list does not support are reported as [`Health::Unsupported`].
+    pub async fn health_check(&self, list: &BlockList) -> HealthReport {
+        let ipv4 = self.test_points(
+            QueryKind::Ipv4,
+            ip_query(list, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))),
+            ip_query(list, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            list,
+        );
+        let ipv6 = self.test_points(
+            QueryKind::Ipv6,
+            ip_query(
+                list,
+                IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x7f00, 2)),
+            ),
+            ip_query(
+                list,
+                IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x7f00, 1)),
+            ),
+            list,
+        );
+        let domain = self.test_points(
+            QueryKind::Domain,
+            domain_query(list, &Domain::new("test").expect("valid domain")),
+            domain_query(list, &Domain::new("invalid").expect("valid domain")),
+            list,
+        );
+
+        let (ipv4, ipv6, domain) = futures::join!(ipv4, ipv6, domain);
+        HealthReport {
+            list: list.clone(),
+            ipv4,
+            ipv6,
+            domain,
+        }
+    }
+
+    async fn test_points(
+        &self,
+        kind: QueryKind,
+        listed: Name,
+        unlisted: Name,
+        list: &BlockList,
+    ) -> Health {
+        if !list.supports(kind) {
+            return Health::Unsupported;
+        }
+        let (listed, unlisted) =
+            futures::join!(self.lookup(list, listed), self.lookup(list, unlisted));
+        Health::from_test_points(&listed.0, &unlisted.0)
+    }
+}
@@ -0,0 +1,273 @@
+//! JSON API for lookups.
+//!
+//! - `GET /v1/check/ip/{ip}?lists=a,b` and `GET /v1/check/domain/{domain}?lists=a,b` check one
+//!   target against the configured lists, or the given subset of them.
+//! - `POST /v1/check` with `{"ips": [...], "domains": [...], "lists": [...]}` checks many targets.
+//! - `GET /healthz` runs [`DNSBL::health_check`] for every configured list and answers `503` if
+//!   any of them is not alive. The reports are reused for [`HttpConfig::health_interval`].
+//!
+//! Results are [`TargetResults`], per-list results use the shape of [`ListResult`](crate::ListResult).
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures::future::{self, Future};
+use serde::{Deserialize, Serialize};
+
+use crate::{BlockList, CheckResults, Domain, HealthReport, DNSBL};
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Lists queried by default, the `lists` parameter may only select among these.
+    pub lists: Vec<BlockList>,
+    /// Upper bound for the time spent on one request.
+    pub timeout: Duration,
+    /// Maximum number of targets in a bulk request.
+    pub max_targets: usize,
+    /// How long `/healthz` answers from the last health checks, zero checks on every request.
+    pub health_interval: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            lists: Vec::new(),
+            timeout: Duration::from_secs(10),
+            max_targets: 100,
+            health_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+struct AppState {
+    dnsbl: Arc<DNSBL>,
+    config: HttpConfig,
+    /// Reports of the last health checks and when they ran.
+    health: Mutex<Option<(Instant, Vec<HealthReport>)>>,
+}
+
+impl AppState {
+    fn lists(&self, selected: Option<Vec<BlockList>>) -> Result<Vec<BlockList>, ApiError> {
+        let selected = match selected {
+            Some(selected) => selected,
+            None => return Ok(self.config.lists.clone()),
+        };
+        // The configured lists carry the metadata, the selection only names them.
+        selected
+            .iter()
+            .map(|list| {
+                self.config
+                    .lists
+                    .iter()
+                    .find(|&configured| configured == list)
+                    .cloned()
+                    .ok_or_else(|| ApiError::BadRequest(format!("unknown list {}", list)))
+            })
+            .collect()
+    }
+
+    fn cached_health(&self) -> Option<Vec<HealthReport>> {
+        let health = self.health.lock().expect("health lock poisoned");
+        health
+            .as_ref()
+            .filter(|(checked, _)| checked.elapsed() < self.config.health_interval)
+            .map(|(_, reports)| reports.clone())
+    }
+
+    async fn with_timeout<F: Future>(&self, future: F) -> Result<F::Output, ApiError> {
+        tokio::time::timeout(self.config.timeout, future)
+            .await
+            .map_err(|_| ApiError::Timeout)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetResults {
+    /// The IP address or domain that was checked.
+    pub target: String,
+    /// Whether any list reports the target.
+    pub listed: bool,
+    pub results: CheckResults,
+}
+
+impl TargetResults {
+    fn new(target: String, results: CheckResults) -> Self {
+        Self {
+            target,
+            listed: results.is_blocked(),
+            results,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkRequest {
+    #[serde(default)]
+    pub ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub domains: Vec<Domain>,
+    #[serde(default)]
+    pub lists: Option<Vec<BlockList>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub healthy: bool,
+    pub lists: Vec<HealthReport>,
+}
+
+#[derive(Deserialize)]
+struct ListsParam {
+    lists: Option<String>,
+}
+
+impl ListsParam {
+    fn parse(self) -> Result<Option<Vec<BlockList>>, ApiError> {
+        self.lists
+            .map(|lists| {
+                lists
+                    .split(',')
+                    .map(|list| {
+                        list.parse()
+                            .map_err(|error: crate::Error| ApiError::BadRequest(error.to_string()))
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+}
+
+enum ApiError {
+    BadRequest(String),
+    Timeout,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            ApiError::BadRequest(error) => (StatusCode::BAD_REQUEST, error),
+            ApiError::Timeout => (StatusCode::GATEWAY_TIMEOUT, "request timed out".to_owned()),
+        };
+        (status, Json(ErrorBody { error })).into_response()
+    }
+}
+
+pub fn router(dnsbl: Arc<DNSBL>, config: HttpConfig) -> Router {
+    Router::new()
+        .route("/v1/check/ip/:ip", get(check_ip))
+        .route("/v1/check/domain/:domain", get(check_domain))
+        .route("/v1/check", post(check_bulk))
+        .route("/healthz", get(healthz))
+        .with_state(Arc::new(AppState {
+            dnsbl,
+            config,
+            health: Mutex::new(None),
+        }))
+}
+
+pub async fn serve(
+    addr: SocketAddr,
+    dnsbl: Arc<DNSBL>,
+    config: HttpConfig,
+) -> Result<(), hyper::Error> {
+    axum::Server::bind(&addr)
+        .serve(router(dnsbl, config).into_make_service())
+        .await
+}
+
+async fn check_ip(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<IpAddr>,
+    Query(params): Query<ListsParam>,
+) -> Result<Json<TargetResults>, ApiError> {
+    let lists = state.lists(params.parse()?)?;
+    let results = state
+        .with_timeout(state.dnsbl.check_ip_all(&lists, ip))
+        .await?;
+    Ok(Json(TargetResults::new(ip.to_string(), results)))
+}
+
+async fn check_domain(
+    State(state): State<Arc<AppState>>,
+    Path(domain): Path<String>,
+    Query(params): Query<ListsParam>,
+) -> Result<Json<TargetResults>, ApiError> {
+    let lists = state.lists(params.parse()?)?;
+    let domain = Domain::new(&domain).map_err(|error| ApiError::BadRequest(error.to_string()))?;
+    let results = state
+        .with_timeout(state.dnsbl.check_domain_all(&lists, &domain))
+        .await?;
+    Ok(Json(TargetResults::new(domain.to_string(), results)))
+}
+
+async fn check_bulk(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BulkRequest>,
+) -> Result<Json<Vec<TargetResults>>, ApiError> {
+    let lists = state.lists(request.lists)?;
+    if request.ips.len() + request.domains.len() > state.config.max_targets {
+        return Err(ApiError::BadRequest(format!(
+            "at most {} targets per request",
+            state.config.max_targets
+        )));
+    }
+
+    let dnsbl = &state.dnsbl;
+    let lists = &lists;
+    let ips = request.ips.iter().map(|&ip| async move {
+        TargetResults::new(ip.to_string(), dnsbl.check_ip_all(lists, ip).await)
+    });
+    let domains = request.domains.iter().map(|domain| async move {
+        TargetResults::new(
+            domain.to_string(),
+            dnsbl.check_domain_all(lists, domain).await,
+        )
+    });
+
+    let (mut ips, domains) = state
+        .with_timeout(future::join(
+            future::join_all(ips),
+            future::join_all(domains),
+        ))
+        .await?;
+    ips.extend(domains);
+    Ok(Json(ips))
+}
+
+async fn healthz(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let lists = match state.cached_health() {
+        Some(lists) => lists,
+        None => {
+            let reports = state
+                .config
+                .lists
+                .iter()
+                .map(|list| state.dnsbl.health_check(list));
+            let lists = state.with_timeout(future::join_all(reports)).await?;
+            *state.health.lock().expect("health lock poisoned") =
+                Some((Instant::now(), lists.clone()));
+            lists
+        }
+    };
+
+    let healthy = lists.iter().all(HealthReport::is_alive);
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((status, Json(HealthResponse { healthy, lists })).into_response())
+}
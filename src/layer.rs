@@ -0,0 +1,250 @@
+//! [`tower`] middleware rejecting requests from listed clients.
+//!
+//! The client is taken from a [`ProxyHeader`] request extension if present, otherwise from
+//! axum's [`ConnectInfo`], so the service has to be served with
+//! `into_make_service_with_connect_info::<SocketAddr>()`, accepting connections through a
+//! [`ProxyAcceptor`](crate::proxy_protocol::ProxyAcceptor) behind a load balancer speaking the
+//! PROXY protocol. Requests from trusted proxies are attributed to the last untrusted address in
+//! their `X-Forwarded-For` header.
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/", get(handler))
+//!     .layer(BlockLayer::new(dnsbl, config));
+//! axum::Server::bind(&addr)
+//!     .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+//!     .await?;
+//! ```
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::{BoxFuture, FutureExt};
+use tower::{Layer, Service};
+
+use crate::cache::ExpiryMap;
+use crate::proxy_protocol::ProxyHeader;
+use crate::{BlockList, BlockStatus, CheckResults, Network, DNSBL};
+
+#[derive(Debug, Clone)]
+pub struct BlockConfig {
+    /// Lists the client address is checked against.
+    pub lists: Vec<BlockList>,
+    /// Peers whose `X-Forwarded-For` header is trusted.
+    pub trusted_proxies: Vec<Network>,
+    /// Reject clients that no list reports if at least one list could not be queried.
+    pub reject_on_error: bool,
+    /// How long the decision for a client is reused. Failed checks are never reused.
+    pub decision_ttl: Duration,
+    /// Maximum number of remembered decisions.
+    pub capacity: usize,
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            lists: Vec::new(),
+            trusted_proxies: Vec::new(),
+            reject_on_error: false,
+            decision_ttl: Duration::from_secs(300),
+            capacity: 10_000,
+        }
+    }
+}
+
+type Responder = dyn Fn(IpAddr, &CheckResults) -> Response + Send + Sync;
+
+/// Results of the check if the client is rejected.
+type Decision = Option<Arc<CheckResults>>;
+
+#[derive(Clone)]
+pub struct BlockLayer {
+    dnsbl: Arc<DNSBL>,
+    config: Arc<BlockConfig>,
+    responder: Arc<Responder>,
+    decisions: Arc<Mutex<ExpiryMap<IpAddr, Decision>>>,
+}
+
+impl BlockLayer {
+    pub fn new(dnsbl: Arc<DNSBL>, config: BlockConfig) -> Self {
+        let decisions = ExpiryMap::new(config.capacity);
+        Self {
+            dnsbl,
+            config: Arc::new(config),
+            responder: Arc::new(default_response),
+            decisions: Arc::new(Mutex::new(decisions)),
+        }
+    }
+
+    /// Replaces the response for rejected clients, which is `403 Forbidden` with the TXT
+    /// messages of the lists, or `503 Service Unavailable` if a list could not be queried.
+    pub fn response<F>(mut self, response: F) -> Self
+    where
+        F: Fn(IpAddr, &CheckResults) -> Response + Send + Sync + 'static,
+    {
+        self.responder = Arc::new(response);
+        self
+    }
+
+    /// Address of the client that sent `request`, `None` if it is unknown.
+    pub fn client_address<B>(&self, request: &Request<B>) -> Option<IpAddr> {
+        let extensions = request.extensions();
+        let peer = match extensions.get::<ProxyHeader>() {
+            Some(header) => header.source?,
+            None => extensions.get::<ConnectInfo<SocketAddr>>()?.0,
+        };
+
+        let mut client = peer.ip();
+        if !self.is_trusted(client) {
+            return Some(client);
+        }
+
+        let forwarded: Vec<&str> = request
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in forwarded.into_iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = ip;
+                    if !self.is_trusted(ip) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// Checks `client` and returns the response if it is rejected.
+    pub async fn check(&self, client: IpAddr) -> Option<Response> {
+        if let Some(rejected) = self.decision(client) {
+            return rejected.map(|results| (self.responder)(client, &results));
+        }
+
+        let results = self.dnsbl.check_ip_all(&self.config.lists, client).await;
+        let failed = results
+            .iter()
+            .any(|result| matches!(result.status, BlockStatus::Error { .. }));
+
+        if results.is_blocked() {
+            let results = Arc::new(results);
+            self.remember(client, Some(results.clone()));
+            Some((self.responder)(client, &results))
+        } else if failed {
+            if self.config.reject_on_error {
+                Some((self.responder)(client, &results))
+            } else {
+                None
+            }
+        } else {
+            self.remember(client, None);
+            None
+        }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.config
+            .trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
+
+    fn decision(&self, client: IpAddr) -> Option<Decision> {
+        let mut decisions = self.decisions.lock().expect("decisions lock poisoned");
+        decisions.get(&client)
+    }
+
+    fn remember(&self, client: IpAddr, rejected: Decision) {
+        if self.config.decision_ttl.as_secs() == 0 {
+            return;
+        }
+
+        let expires = Instant::now() + self.config.decision_ttl;
+        let mut decisions = self.decisions.lock().expect("decisions lock poisoned");
+        decisions.insert(client, rejected, expires);
+    }
+}
+
+impl<S> Layer<S> for BlockLayer {
+    type Service = BlockService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BlockService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockService<S> {
+    inner: S,
+    layer: BlockLayer,
+}
+
+impl<S, B> Service<Request<B>> for BlockService<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The ready service has to handle the request, leave a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let client = layer.client_address(&request);
+
+        async move {
+            if let Some(client) = client {
+                if let Some(response) = layer.check(client).await {
+                    return Ok(response);
+                }
+            }
+            inner.call(request).await.map(IntoResponse::into_response)
+        }
+        .boxed()
+    }
+}
+
+fn default_response(client: IpAddr, results: &CheckResults) -> Response {
+    if !results.is_blocked() {
+        let text = "Temporary failure checking the client address\n";
+        return (StatusCode::SERVICE_UNAVAILABLE, text).into_response();
+    }
+
+    let text: String = results
+        .iter()
+        .filter_map(|result| match &result.status {
+            BlockStatus::Blocked {
+                message: Some(message),
+                ..
+            } => Some(format!("{}\n", message)),
+            BlockStatus::Blocked { .. } => {
+                Some(format!("{} is listed by {}\n", client, result.list))
+            }
+            _ => None,
+        })
+        .collect();
+    (StatusCode::FORBIDDEN, text).into_response()
+}
@@ -1,17 +1,67 @@
+use std::collections::BTreeSet;
 use std::fmt;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
 
 use trust_dns_resolver::{
-    config::{ResolverConfig, ResolverOpts},
-    error::ResolveError,
-    Name, TokioAsyncResolver,
+    config::{NameServerConfig, NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    proto::op::ResponseCode,
+    system_conf, TokioAsyncResolver,
 };
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-pub type Error = ResolveError;
+#[cfg(feature = "analysis")]
+pub mod analysis;
+mod cache;
+mod hash;
+mod health;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "http")]
+pub mod layer;
+mod list;
+#[cfg(feature = "listener")]
+pub mod listener;
+mod lookup;
+mod network;
+#[cfg(feature = "policy")]
+pub mod policy;
+#[cfg(feature = "server")]
+pub mod proxy;
+#[cfg(feature = "http")]
+pub mod proxy_protocol;
+pub mod rbldnsd;
+pub mod received;
+mod return_codes;
+pub mod rhsbl;
+pub mod score;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "test-server")]
+pub mod test_server;
+pub mod uri;
+#[cfg(any(feature = "server", feature = "test-server"))]
+mod wire;
+
+pub use cache::{CacheConfig, CacheStats};
+pub use hash::{HashAlgorithm, HashDescriptor, HashEncoding};
+pub use health::{Health, HealthReport};
+pub use list::{BlockList, QueryKind};
+pub use lookup::{Answer, Lookup, MemoryLookup};
+pub use network::{AddressClass, AddressPolicy, Network};
+pub use return_codes::ReturnCodes;
+pub use trust_dns_resolver::Name;
 
-pub type BlockList = Domain;
+use cache::Cache;
+use rhsbl::DomainLevel;
+
+pub type Error = ResolveError;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Domain(Name);
@@ -23,6 +73,14 @@ impl Domain {
     }
 }
 
+impl FromStr for Domain {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
 impl fmt::Display for Domain {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
@@ -43,60 +101,580 @@ impl<'de> Deserialize<'de> for Domain {
 }
 
 pub struct DNSBL {
-    resolver: TokioAsyncResolver,
+    backend: Arc<dyn Lookup>,
+    concurrency: usize,
+    cache: Option<Cache>,
+    domain_level: DomainLevel,
+    address_policy: AddressPolicy,
 }
 
+const DEFAULT_CONCURRENCY: usize = 8;
+
 impl DNSBL {
     pub fn new() -> Result<Self, Error> {
-        let resolver =
-            TokioAsyncResolver::tokio(ResolverConfig::cloudflare_tls(), ResolverOpts::default())?;
-        Ok(Self { resolver })
+        Self::with_config(ResolverConfig::cloudflare_tls(), ResolverOpts::default())
     }
 
-    pub async fn check_domain(&self, list: &BlockList, domain: &Domain) -> BlockStatus {
-        let dns_name =
-            Name::from_labels(domain.0.into_iter().chain(&list.0)).expect("always valid");
+    pub fn with_config(config: ResolverConfig, options: ResolverOpts) -> Result<Self, Error> {
+        let resolver = TokioAsyncResolver::tokio(config, options)?;
+        Ok(Self::from_backend(Arc::new(resolver)))
+    }
 
-        self.check(dns_name).await
+    /// Uses `lookup` instead of a trust-dns resolver for all queries.
+    pub fn with_lookup<L: Lookup + 'static>(lookup: L) -> Self {
+        Self::from_backend(Arc::new(lookup))
     }
 
-    pub async fn check_ip<A: Into<IpAddr>>(&self, list: &BlockList, ip_addr: A) -> BlockStatus {
-        let ip: Name = ip_addr.into().into();
+    fn from_backend(backend: Arc<dyn Lookup>) -> Self {
+        Self {
+            backend,
+            concurrency: DEFAULT_CONCURRENCY,
+            cache: None,
+            domain_level: DomainLevel::default(),
+            address_policy: AddressPolicy::default(),
+        }
+    }
 
-        let dns_name = Name::from_labels(
-            ip.into_iter()
-                .take(usize::from(ip.num_labels() - 2))
-                .chain(&list.0),
-        )
-        .expect("always valid");
+    pub fn builder() -> DNSBLBuilder {
+        DNSBLBuilder::default()
+    }
 
-        self.check(dns_name).await
+    pub async fn check_domain(&self, list: &BlockList, domain: &Domain) -> BlockStatus {
+        if !list.supports(QueryKind::Domain) {
+            return BlockStatus::NotApplicable;
+        }
+        self.check(list, domain_query(list, domain)).await
     }
 
-    async fn check(&self, dns_name: Name) -> BlockStatus {
-        if self.resolver.ipv4_lookup(dns_name.clone()).await.is_err() {
-            BlockStatus::NotBlocked
-        } else if let Ok(txt) = self.resolver.txt_lookup(dns_name).await {
-            let message = txt
+    /// Checks `ip_addr`, unless the [`AddressPolicy`] skips it or the list does not support its
+    /// address family.
+    pub async fn check_ip<A: Into<IpAddr>>(&self, list: &BlockList, ip_addr: A) -> BlockStatus {
+        let ip_addr = self.address_policy.normalize(ip_addr.into());
+        if !self.address_policy.applies(ip_addr) || !list.supports(QueryKind::of(ip_addr)) {
+            return BlockStatus::NotApplicable;
+        }
+        self.check(list, ip_query(list, ip_addr)).await
+    }
+
+    /// Checks `domain` against every list, running at most the configured number of lookups
+    /// at the same time.
+    pub async fn check_domain_all(&self, lists: &[BlockList], domain: &Domain) -> CheckResults {
+        self.check_all(lists, QueryKind::Domain, |list| domain_query(list, domain))
+            .await
+    }
+
+    /// Checks `ip_addr` against every list, running at most the configured number of lookups
+    /// at the same time. Addresses skipped by the [`AddressPolicy`] are not queried.
+    pub async fn check_ip_all<A: Into<IpAddr>>(
+        &self,
+        lists: &[BlockList],
+        ip_addr: A,
+    ) -> CheckResults {
+        let ip_addr = self.address_policy.normalize(ip_addr.into());
+        if !self.address_policy.applies(ip_addr) {
+            let results = lists
                 .iter()
-                .map(|i| {
-                    i.iter()
-                        .map(|i| String::from_utf8(i.to_vec()).unwrap_or_else(|_| "".to_owned()))
-                        .collect::<Vec<_>>()
-                        .join(" ")
+                .map(|list| ListResult {
+                    list: list.clone(),
+                    query: Domain(ip_query(list, ip_addr)),
+                    status: BlockStatus::NotApplicable,
+                    elapsed: Duration::ZERO,
                 })
-                .collect::<Vec<_>>()
-                .join(" ");
-            BlockStatus::Blocked {
-                message: Some(message).filter(|s| !s.is_empty()),
+                .collect();
+            return CheckResults(results);
+        }
+        self.query_ip_all(lists, ip_addr).await
+    }
+
+    /// Checks `ip_addr` against every list as given, regardless of the [`AddressPolicy`].
+    pub(crate) async fn query_ip_all(&self, lists: &[BlockList], ip_addr: IpAddr) -> CheckResults {
+        self.check_all(lists, QueryKind::of(ip_addr), |list| {
+            ip_query(list, ip_addr)
+        })
+        .await
+    }
+
+    /// Queries every list supporting `kind`, the others are answered with
+    /// [`BlockStatus::NotApplicable`].
+    async fn check_all<F>(&self, lists: &[BlockList], kind: QueryKind, query: F) -> CheckResults
+    where
+        F: Fn(&BlockList) -> Name,
+    {
+        let results = stream::iter(lists)
+            .map(|list| {
+                let dns_name = query(list);
+                async move {
+                    let start = Instant::now();
+                    let status = if list.supports(kind) {
+                        self.check(list, dns_name.clone()).await
+                    } else {
+                        BlockStatus::NotApplicable
+                    };
+                    ListResult {
+                        list: list.clone(),
+                        query: Domain(dns_name),
+                        status,
+                        elapsed: start.elapsed(),
+                    }
+                }
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        CheckResults(results)
+    }
+
+    async fn check(&self, list: &BlockList, dns_name: Name) -> BlockStatus {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.lookup(list, dns_name).await.0,
+        };
+
+        if let Some(status) = cache.get(&list.domain, &dns_name) {
+            return status;
+        }
+
+        let (status, ttl) = self.lookup(list, dns_name.clone()).await;
+        cache.insert(list.domain.clone(), dns_name, status.clone(), ttl);
+        status
+    }
+
+    /// Queries the list and returns the result together with the TTL of the answer.
+    async fn lookup(&self, list: &BlockList, dns_name: Name) -> (BlockStatus, Option<Duration>) {
+        let answer = match self.backend.lookup_a(dns_name.clone()).await {
+            Ok(answer) if answer.records.is_empty() => {
+                return (BlockStatus::NotBlocked, answer.ttl)
+            }
+            Ok(answer) => answer,
+            Err(error) => {
+                let ttl = negative_ttl(&error);
+                return (BlockStatus::from_error(error), ttl);
+            }
+        };
+        let addresses = answer.records;
+
+        if let Some(reason) = list.return_codes.error(&addresses) {
+            let error = Error::from(format!("{} refused the query: {}", list, reason));
+            let status = BlockStatus::Error {
+                kind: ErrorKind::Refused,
+                source: Arc::new(error),
+            };
+            return (status, None);
+        }
+        let reasons = list.return_codes.decode(&addresses);
+
+        let message = match self.backend.lookup_txt(dns_name).await {
+            Ok(txt) => Some(txt.records.join(" ")).filter(|s| !s.is_empty()),
+            Err(_) => None,
+        };
+
+        let status = BlockStatus::Blocked {
+            addresses,
+            reasons,
+            message,
+        };
+
+        (status, answer.ttl)
+    }
+
+    /// Hit and miss counters of the result cache, `None` if caching is disabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(Cache::stats)
+    }
+
+    /// Which addresses [`check_ip`](Self::check_ip) sends to lists.
+    pub fn address_policy(&self) -> &AddressPolicy {
+        &self.address_policy
+    }
+}
+
+fn domain_query(list: &BlockList, domain: &Domain) -> Name {
+    Name::from_labels(domain.0.into_iter().chain(&list.domain.0)).expect("always valid")
+}
+
+fn ip_query(list: &BlockList, ip_addr: IpAddr) -> Name {
+    let ip: Name = ip_addr.into();
+
+    Name::from_labels(
+        ip.into_iter()
+            .take(usize::from(ip.num_labels() - 2))
+            .chain(&list.domain.0),
+    )
+    .expect("always valid")
+}
+
+fn negative_ttl(error: &Error) -> Option<Duration> {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { negative_ttl, .. } => {
+            negative_ttl.map(|ttl| Duration::from_secs(u64::from(ttl)))
+        }
+        _ => None,
+    }
+}
+
+/// Configures the resolver used by [`DNSBL`].
+///
+/// Without any upstream configured the builder falls back to Cloudflare over TLS, matching
+/// [`DNSBL::new`]. Name servers added with the `*_name_servers` methods are appended to the
+/// system or explicit resolver configuration.
+#[derive(Default)]
+pub struct DNSBLBuilder {
+    system_conf: bool,
+    config: Option<ResolverConfig>,
+    name_servers: Vec<NameServerConfig>,
+    options: Option<ResolverOpts>,
+    concurrency: Option<usize>,
+    cache: Option<CacheConfig>,
+    domain_level: DomainLevel,
+    address_policy: AddressPolicy,
+    backend: Option<Arc<dyn Lookup>>,
+}
+
+impl DNSBLBuilder {
+    /// Use the name servers and options from the system configuration (`/etc/resolv.conf`).
+    pub fn system_conf(mut self) -> Self {
+        self.system_conf = true;
+        self
+    }
+
+    pub fn resolver_config(mut self, config: ResolverConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Plain DNS name servers, queried over UDP with TCP fallback.
+    pub fn name_servers(mut self, ips: &[IpAddr], port: u16) -> Self {
+        let group = NameServerConfigGroup::from_ips_clear(ips, port, true);
+        self.name_servers.extend(group.iter().cloned());
+        self
+    }
+
+    /// DNS over TLS name servers, `tls_dns_name` is used to verify their certificates.
+    pub fn tls_name_servers<S: Into<String>>(
+        mut self,
+        ips: &[IpAddr],
+        port: u16,
+        tls_dns_name: S,
+    ) -> Self {
+        let group = NameServerConfigGroup::from_ips_tls(ips, port, tls_dns_name.into(), true);
+        self.name_servers.extend(group.iter().cloned());
+        self
+    }
+
+    /// DNS over HTTPS name servers, `tls_dns_name` is used to verify their certificates.
+    pub fn https_name_servers<S: Into<String>>(
+        mut self,
+        ips: &[IpAddr],
+        port: u16,
+        tls_dns_name: S,
+    ) -> Self {
+        let group = NameServerConfigGroup::from_ips_https(ips, port, tls_dns_name.into(), true);
+        self.name_servers.extend(group.iter().cloned());
+        self
+    }
+
+    pub fn options(mut self, options: ResolverOpts) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.get_or_insert_with(Default::default).timeout = timeout;
+        self
+    }
+
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.options.get_or_insert_with(Default::default).attempts = attempts;
+        self
+    }
+
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.options.get_or_insert_with(Default::default).cache_size = cache_size;
+        self
+    }
+
+    /// Maximum number of lists queried at the same time by [`DNSBL::check_ip_all`] and
+    /// [`DNSBL::check_domain_all`].
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Enables the in-process result cache.
+    pub fn cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(config);
+        self
+    }
+
+    /// Which names [`DNSBL::check_rhsbl_all`] and [`DNSBL::check_email_sender`] query,
+    /// the registrable domain by default.
+    pub fn domain_level(mut self, level: DomainLevel) -> Self {
+        self.domain_level = level;
+        self
+    }
+
+    /// Which addresses are sent to lists, by default no special purpose addresses.
+    pub fn address_policy(mut self, policy: AddressPolicy) -> Self {
+        self.address_policy = policy;
+        self
+    }
+
+    /// Uses `lookup` for all queries, the resolver configuration is ignored.
+    pub fn lookup<L: Lookup + 'static>(mut self, lookup: L) -> Self {
+        self.backend = Some(Arc::new(lookup));
+        self
+    }
+
+    pub fn build(self) -> Result<DNSBL, Error> {
+        let mut dnsbl = match self.backend {
+            Some(backend) => DNSBL::from_backend(backend),
+            None => {
+                let (mut config, system_options) = if self.system_conf {
+                    let (config, options) = system_conf::read_system_conf()?;
+                    (config, Some(options))
+                } else if let Some(config) = self.config {
+                    (config, None)
+                } else if self.name_servers.is_empty() {
+                    (ResolverConfig::cloudflare_tls(), None)
+                } else {
+                    (ResolverConfig::new(), None)
+                };
+
+                for name_server in self.name_servers {
+                    config.add_name_server(name_server);
+                }
+
+                let options = self.options.or(system_options).unwrap_or_default();
+
+                DNSBL::with_config(config, options)?
             }
-        } else {
-            BlockStatus::Blocked { message: None }
+        };
+
+        if let Some(concurrency) = self.concurrency {
+            dnsbl.concurrency = concurrency.max(1);
         }
+        dnsbl.cache = self.cache.map(Cache::new);
+        dnsbl.domain_level = self.domain_level;
+        dnsbl.address_policy = self.address_policy;
+        Ok(dnsbl)
     }
 }
-#[derive(PartialEq)]
+
+/// Outcome of a single lookup.
+///
+/// Serialized with a `status` tag of `blocked`, `not_blocked`, `not_applicable` or `error`:
+///
+/// ```json
+/// {"status": "blocked", "addresses": ["127.0.0.2"], "reasons": ["SBL"], "message": "..."}
+/// {"status": "not_blocked"}
+/// {"status": "not_applicable"}
+/// {"status": "error", "kind": "timeout", "error": "request timed out"}
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
 pub enum BlockStatus {
-    Blocked { message: Option<String> },
+    Blocked {
+        /// Every `127.0.0.x` address returned by the list.
+        addresses: Vec<Ipv4Addr>,
+        /// Reasons decoded from `addresses` with the list's [`ReturnCodes`].
+        #[serde(default)]
+        reasons: BTreeSet<String>,
+        #[serde(default)]
+        message: Option<String>,
+    },
     NotBlocked,
+    /// Nothing was queried because the [`AddressPolicy`] skips the address or the list does not
+    /// support the kind of query.
+    NotApplicable,
+    /// The list could not be queried, so it is unknown whether the address is listed.
+    Error {
+        kind: ErrorKind,
+        #[serde(rename = "error", with = "error_message")]
+        source: Arc<Error>,
+    },
+}
+
+impl BlockStatus {
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, BlockStatus::Blocked { .. })
+    }
+
+    fn from_error(error: Error) -> Self {
+        let kind = match error.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. } => match *response_code {
+                ResponseCode::NoError | ResponseCode::NXDomain => return BlockStatus::NotBlocked,
+                ResponseCode::ServFail => ErrorKind::ServerFailure,
+                ResponseCode::Refused => ErrorKind::Refused,
+                _ => ErrorKind::Other,
+            },
+            ResolveErrorKind::Timeout => ErrorKind::Timeout,
+            ResolveErrorKind::NoConnections => ErrorKind::NoConnections,
+            ResolveErrorKind::Io(_) => ErrorKind::Io,
+            ResolveErrorKind::Proto(_) => ErrorKind::Protocol,
+            _ => ErrorKind::Other,
+        };
+
+        BlockStatus::Error {
+            kind,
+            source: Arc::new(error),
+        }
+    }
+}
+
+impl PartialEq for BlockStatus {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                BlockStatus::Blocked {
+                    addresses,
+                    reasons,
+                    message,
+                },
+                BlockStatus::Blocked {
+                    addresses: other_addresses,
+                    reasons: other_reasons,
+                    message: other_message,
+                },
+            ) => {
+                addresses == other_addresses && reasons == other_reasons && message == other_message
+            }
+            (BlockStatus::NotBlocked, BlockStatus::NotBlocked) => true,
+            (BlockStatus::NotApplicable, BlockStatus::NotApplicable) => true,
+            (
+                BlockStatus::Error { kind, .. },
+                BlockStatus::Error {
+                    kind: other_kind, ..
+                },
+            ) => kind == other_kind,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// No answer was received in time.
+    Timeout,
+    /// No name server could be reached.
+    NoConnections,
+    /// The name server answered with `SERVFAIL`.
+    ServerFailure,
+    /// The name server answered with `REFUSED`, or the list answered with one of the error codes
+    /// of its [`ReturnCodes`].
+    Refused,
+    /// Network or TLS failure.
+    Io,
+    /// Malformed or unexpected DNS message.
+    Protocol,
+    Other,
+}
+
+/// Result of one list in a multi-list check.
+///
+/// Serialized as the list, the queried name and the elapsed milliseconds next to the fields of
+/// the [`BlockStatus`]:
+///
+/// ```json
+/// {
+///   "list": "zen.spamhaus.org",
+///   "query": "2.0.0.127.zen.spamhaus.org",
+///   "status": "blocked",
+///   "addresses": ["127.0.0.2", "127.0.0.4"],
+///   "reasons": ["SBL", "XBL"],
+///   "message": "https://www.spamhaus.org/query/ip/127.0.0.2",
+///   "elapsed_ms": 12
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListResult {
+    #[serde(with = "list::list_name")]
+    pub list: BlockList,
+    /// Name that was looked up.
+    pub query: Domain,
+    #[serde(flatten)]
+    pub status: BlockStatus,
+    /// Time spent waiting for the answers of this list.
+    #[serde(rename = "elapsed_ms", with = "duration_ms")]
+    pub elapsed: Duration,
+}
+
+/// Per-list results of a multi-list check, in the order the lists were given.
+///
+/// Serialized as an array of [`ListResult`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CheckResults(Vec<ListResult>);
+
+impl CheckResults {
+    pub fn get(&self, list: &BlockList) -> Option<&ListResult> {
+        self.0.iter().find(|result| &result.list == list)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ListResult> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether any of the lists reported the address as blocked.
+    pub fn is_blocked(&self) -> bool {
+        self.0.iter().any(|result| result.status.is_blocked())
+    }
+}
+
+impl IntoIterator for CheckResults {
+    type Item = ListResult;
+    type IntoIter = std::vec::IntoIter<ListResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CheckResults {
+    type Item = &'a ListResult;
+    type IntoIter = std::slice::Iter<'a, ListResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+mod error_message {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::Error;
+
+    pub fn serialize<S: Serializer>(error: &Arc<Error>, serializer: S) -> Result<S::Ok, S::Error> {
+        error.to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<Error>, D::Error> {
+        let message = String::deserialize(deserializer)?;
+        Ok(Arc::new(Error::from(message)))
+    }
+}
+
+mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        (duration.as_millis() as u64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
 }
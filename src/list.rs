@@ -0,0 +1,300 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Domain, Error, HashDescriptor, ReturnCodes};
+
+/// Kind of value a list can be queried for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryKind {
+    Ipv4,
+    Ipv6,
+    Domain,
+    /// Digests, see [`DNSBL::check_hash`](crate::DNSBL::check_hash).
+    Hash,
+}
+
+impl QueryKind {
+    pub const ALL: [QueryKind; 4] = [
+        QueryKind::Ipv4,
+        QueryKind::Ipv6,
+        QueryKind::Domain,
+        QueryKind::Hash,
+    ];
+
+    pub(crate) fn of(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => QueryKind::Ipv4,
+            IpAddr::V6(_) => QueryKind::Ipv6,
+        }
+    }
+}
+
+/// A list together with what is known about it.
+///
+/// Queries of a kind the list does not support are answered with
+/// [`BlockStatus::NotApplicable`](crate::BlockStatus::NotApplicable) without sending anything.
+/// Lists are compared and hashed by their domain only.
+///
+/// Parsed from a bare domain, which supports every kind of query. Deserializes from either a
+/// bare domain or an object:
+///
+/// ```json
+/// {
+///   "domain": "zen.spamhaus.org",
+///   "kinds": ["ipv4", "ipv6"],
+///   "return_codes": [{"first": "127.0.0.2", "last": "127.0.0.2", "reason": "SBL"}],
+///   "hash_descriptor": {"algorithm": "sha1", "encoding": "hex", "lowercase": true},
+///   "description": "Spamhaus ZEN",
+///   "website": "https://www.spamhaus.org/blocklists/zen-blocklist/",
+///   "delisting_url": "https://check.spamhaus.org/"
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Repr")]
+pub struct BlockList {
+    pub domain: Domain,
+    pub kinds: BTreeSet<QueryKind>,
+    /// Decodes the answers of the list into listing reasons.
+    pub return_codes: ReturnCodes,
+    /// How [`DNSBL::check_hash`](crate::DNSBL::check_hash) builds query names, see
+    /// [`HashDescriptor::validate`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_descriptor: Option<HashDescriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delisting_url: Option<String>,
+}
+
+impl BlockList {
+    /// A list supporting every kind of query, without return codes.
+    pub fn new(domain: Domain) -> Self {
+        Self {
+            domain,
+            kinds: all_kinds(),
+            return_codes: ReturnCodes::new(),
+            hash_descriptor: None,
+            description: None,
+            website: None,
+            delisting_url: None,
+        }
+    }
+
+    pub fn kinds(mut self, kinds: &[QueryKind]) -> Self {
+        self.kinds = kinds.iter().copied().collect();
+        self
+    }
+
+    pub fn return_codes(mut self, return_codes: ReturnCodes) -> Self {
+        self.return_codes = return_codes;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If the digests or the label of `descriptor` do not fit into a DNS name.
+    pub fn hash_descriptor(mut self, descriptor: HashDescriptor) -> Self {
+        if let Err(error) = descriptor.validate() {
+            panic!("{}", error);
+        }
+        self.hash_descriptor = Some(descriptor);
+        self
+    }
+
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn website<S: Into<String>>(mut self, website: S) -> Self {
+        self.website = Some(website.into());
+        self
+    }
+
+    pub fn delisting_url<S: Into<String>>(mut self, delisting_url: S) -> Self {
+        self.delisting_url = Some(delisting_url.into());
+        self
+    }
+
+    pub fn supports(&self, kind: QueryKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    /// `zen.spamhaus.org`
+    pub fn spamhaus_zen() -> Self {
+        Self::well_known("zen.spamhaus.org")
+            .kinds(&[QueryKind::Ipv4, QueryKind::Ipv6])
+            .return_codes(ReturnCodes::spamhaus_zen())
+            .description("Spamhaus ZEN")
+            .website("https://www.spamhaus.org/blocklists/zen-blocklist/")
+            .delisting_url("https://check.spamhaus.org/")
+    }
+
+    /// `dbl.spamhaus.org`
+    pub fn spamhaus_dbl() -> Self {
+        Self::well_known("dbl.spamhaus.org")
+            .kinds(&[QueryKind::Domain])
+            .return_codes(ReturnCodes::spamhaus_dbl())
+            .description("Spamhaus Domain Blocklist")
+            .website("https://www.spamhaus.org/blocklists/domain-blocklist/")
+            .delisting_url("https://check.spamhaus.org/")
+    }
+
+    /// `multi.surbl.org`
+    pub fn surbl_multi() -> Self {
+        Self::well_known("multi.surbl.org")
+            .kinds(&[QueryKind::Ipv4, QueryKind::Domain])
+            .return_codes(ReturnCodes::surbl_multi())
+            .description("SURBL multi")
+            .website("https://www.surbl.org/")
+            .delisting_url("https://www.surbl.org/surbl-analysis")
+    }
+
+    /// `multi.uribl.com`
+    pub fn uribl_multi() -> Self {
+        Self::well_known("multi.uribl.com")
+            .kinds(&[QueryKind::Domain])
+            .return_codes(ReturnCodes::uribl_multi())
+            .description("URIBL multi")
+            .website("https://uribl.com/")
+            .delisting_url("https://admin.uribl.com/")
+    }
+
+    /// `<key>.hbl.dq.spamhaus.net` email addresses, Spamhaus only answers queries under a Data
+    /// Query Service key.
+    pub fn spamhaus_hbl(key: &str) -> Result<Self, Error> {
+        if key.is_empty() || key.contains('.') {
+            return Err(Error::from(format!(
+                "invalid Data Query Service key {:?}",
+                key
+            )));
+        }
+        let domain = Domain::new(format!("{}.hbl.dq.spamhaus.net", key))?;
+        let list = Self::new(domain)
+            .kinds(&[QueryKind::Hash])
+            .hash_descriptor(HashDescriptor::spamhaus_hbl_email())
+            .description("Spamhaus Hash Blocklist")
+            .website("https://www.spamhaus.org/blocklists/hash-blocklist/");
+        Ok(list)
+    }
+
+    /// `ebl.msbl.org`
+    pub fn msbl_ebl() -> Self {
+        Self::well_known("ebl.msbl.org")
+            .kinds(&[QueryKind::Hash])
+            .hash_descriptor(HashDescriptor::msbl_ebl())
+            .description("MSBL Email Blocklist")
+            .website("https://msbl.org/")
+    }
+
+    fn well_known(domain: &str) -> Self {
+        Self::new(Domain::new(domain).expect("valid domain"))
+    }
+}
+
+impl From<Domain> for BlockList {
+    fn from(domain: Domain) -> Self {
+        Self::new(domain)
+    }
+}
+
+impl FromStr for BlockList {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Domain::new(s).map(Self::new)
+    }
+}
+
+impl fmt::Display for BlockList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.domain.fmt(f)
+    }
+}
+
+impl PartialEq for BlockList {
+    fn eq(&self, other: &Self) -> bool {
+        self.domain == other.domain
+    }
+}
+
+impl Eq for BlockList {}
+
+impl Hash for BlockList {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.domain.hash(state)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Domain(Domain),
+    List {
+        domain: Domain,
+        #[serde(default = "all_kinds")]
+        kinds: BTreeSet<QueryKind>,
+        #[serde(default)]
+        return_codes: ReturnCodes,
+        #[serde(default)]
+        hash_descriptor: Option<HashDescriptor>,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        website: Option<String>,
+        #[serde(default)]
+        delisting_url: Option<String>,
+    },
+}
+
+fn all_kinds() -> BTreeSet<QueryKind> {
+    QueryKind::ALL.iter().copied().collect()
+}
+
+impl From<Repr> for BlockList {
+    fn from(repr: Repr) -> Self {
+        match repr {
+            Repr::Domain(domain) => Self::new(domain),
+            Repr::List {
+                domain,
+                kinds,
+                return_codes,
+                hash_descriptor,
+                description,
+                website,
+                delisting_url,
+            } => Self {
+                domain,
+                kinds,
+                return_codes,
+                hash_descriptor,
+                description,
+                website,
+                delisting_url,
+            },
+        }
+    }
+}
+
+/// Serializes a list as its domain, used where results refer to a list.
+pub(crate) mod list_name {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{BlockList, Domain};
+
+    pub fn serialize<S: Serializer>(list: &BlockList, serializer: S) -> Result<S::Ok, S::Error> {
+        list.domain.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlockList, D::Error> {
+        Domain::deserialize(deserializer).map(BlockList::new)
+    }
+}
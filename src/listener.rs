@@ -0,0 +1,182 @@
+//! Accepting TCP connections only from peers that no list reports.
+//!
+//! ```no_run
+//! # async fn example(dnsbl: std::sync::Arc<dnsbl::DNSBL>) -> std::io::Result<()> {
+//! use dnsbl::listener::{FilteredListener, ListenerConfig};
+//! use dnsbl::BlockList;
+//!
+//! let config = ListenerConfig {
+//!     lists: vec![BlockList::spamhaus_zen()],
+//!     banner: Some("554 Your address is listed\r\n".to_owned()),
+//!     ..ListenerConfig::default()
+//! };
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:25").await?;
+//! let mut listener = FilteredListener::new(listener, dnsbl, config)?;
+//! loop {
+//!     let (stream, peer) = listener.accept().await?;
+//!     // ...
+//! }
+//! # }
+//! ```
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::{BlockList, BlockStatus, DNSBL};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    /// Lists every peer is checked against.
+    pub lists: Vec<BlockList>,
+    /// Verdict if any list reports the peer.
+    pub on_listed: Verdict,
+    /// Verdict if no list reports the peer but at least one could not be queried.
+    pub on_error: Verdict,
+    /// Verdict if the check does not finish within `timeout`.
+    pub on_timeout: Verdict,
+    pub timeout: Duration,
+    /// Written to rejected peers before the connection is closed.
+    pub banner: Option<String>,
+    /// Maximum number of connections waiting for their check. Further connections stay in the
+    /// listen backlog of the kernel until a check finishes.
+    pub max_pending: usize,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            lists: Vec::new(),
+            on_listed: Verdict::Reject,
+            on_error: Verdict::Accept,
+            on_timeout: Verdict::Accept,
+            timeout: Duration::from_secs(5),
+            banner: None,
+            max_pending: 256,
+        }
+    }
+}
+
+type Accepted = io::Result<(TcpStream, SocketAddr)>;
+
+/// Wraps a [`TcpListener`], checking peers in the background as soon as they connect.
+///
+/// The background task stops when the `FilteredListener` is dropped.
+pub struct FilteredListener {
+    accepted: mpsc::Receiver<Accepted>,
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl FilteredListener {
+    pub fn new(
+        listener: TcpListener,
+        dnsbl: Arc<DNSBL>,
+        config: ListenerConfig,
+    ) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let max_pending = config.max_pending.max(1);
+        let (sender, accepted) = mpsc::channel(max_pending);
+        let task = tokio::spawn(run(listener, dnsbl, Arc::new(config), max_pending, sender));
+
+        Ok(Self {
+            accepted,
+            local_addr,
+            task,
+        })
+    }
+
+    /// Returns the next connection that was not rejected, or the error of a failed accept.
+    pub async fn accept(&mut self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.accepted
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(io::Error::other("listener stopped")))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for FilteredListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(
+    listener: TcpListener,
+    dnsbl: Arc<DNSBL>,
+    config: Arc<ListenerConfig>,
+    max_pending: usize,
+    sender: mpsc::Sender<Accepted>,
+) {
+    let pending = Arc::new(Semaphore::new(max_pending));
+    loop {
+        let permit = match pending.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                if sender.send(Err(error)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let dnsbl = dnsbl.clone();
+        let config = config.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            match verdict(&dnsbl, &config, peer.ip()).await {
+                Verdict::Accept => {
+                    let _ = sender.send(Ok((stream, peer))).await;
+                }
+                Verdict::Reject => reject(stream, &config).await,
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn verdict(dnsbl: &DNSBL, config: &ListenerConfig, peer: IpAddr) -> Verdict {
+    let checked = tokio::time::timeout(config.timeout, dnsbl.check_ip_all(&config.lists, peer));
+    match checked.await {
+        Err(_) => config.on_timeout,
+        Ok(results) if results.is_blocked() => config.on_listed,
+        Ok(results)
+            if results
+                .iter()
+                .any(|result| matches!(result.status, BlockStatus::Error { .. })) =>
+        {
+            config.on_error
+        }
+        Ok(_) => Verdict::Accept,
+    }
+}
+
+async fn reject(mut stream: TcpStream, config: &ListenerConfig) {
+    let close = async {
+        if let Some(banner) = &config.banner {
+            stream.write_all(banner.as_bytes()).await?;
+        }
+        stream.shutdown().await
+    };
+    let _ = tokio::time::timeout(config.timeout, close).await;
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use trust_dns_resolver::{error::ResolveErrorKind, proto::op::ResponseCode, TokioAsyncResolver};
+
+use crate::{domain_query, ip_query, negative_ttl, BlockList, Domain, Error, Name};
+
+/// Records of a successful lookup.
+///
+/// An empty answer means the name does not exist, i.e. nothing is listed; `ttl` is then the
+/// negative caching TTL of the list, if known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer<T> {
+    pub records: Vec<T>,
+    pub ttl: Option<Duration>,
+}
+
+impl<T> Answer<T> {
+    pub fn empty(ttl: Option<Duration>) -> Self {
+        Self {
+            records: Vec::new(),
+            ttl,
+        }
+    }
+}
+
+/// DNS backend used by [`DNSBL`](crate::DNSBL).
+///
+/// Errors are reserved for failed lookups, a name that does not exist must be reported as an
+/// empty [`Answer`].
+pub trait Lookup: Send + Sync {
+    fn lookup_a(&self, name: Name) -> BoxFuture<'_, Result<Answer<Ipv4Addr>, Error>>;
+
+    /// Each record is returned with its character strings joined by spaces.
+    fn lookup_txt(&self, name: Name) -> BoxFuture<'_, Result<Answer<String>, Error>>;
+}
+
+impl Lookup for TokioAsyncResolver {
+    fn lookup_a(&self, name: Name) -> BoxFuture<'_, Result<Answer<Ipv4Addr>, Error>> {
+        Box::pin(async move {
+            match self.ipv4_lookup(name).await {
+                Ok(lookup) => {
+                    let ttl = lookup
+                        .valid_until()
+                        .saturating_duration_since(Instant::now());
+                    Ok(Answer {
+                        records: lookup.into_iter().collect(),
+                        ttl: Some(ttl),
+                    })
+                }
+                Err(error) => not_found(error),
+            }
+        })
+    }
+
+    fn lookup_txt(&self, name: Name) -> BoxFuture<'_, Result<Answer<String>, Error>> {
+        Box::pin(async move {
+            match self.txt_lookup(name).await {
+                Ok(lookup) => {
+                    let ttl = lookup
+                        .valid_until()
+                        .saturating_duration_since(Instant::now());
+                    let records = lookup
+                        .iter()
+                        .map(|i| {
+                            i.iter()
+                                .map(|i| {
+                                    String::from_utf8(i.to_vec()).unwrap_or_else(|_| "".to_owned())
+                                })
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        })
+                        .collect();
+                    Ok(Answer {
+                        records,
+                        ttl: Some(ttl),
+                    })
+                }
+                Err(error) => not_found(error),
+            }
+        })
+    }
+}
+
+fn not_found<T>(error: Error) -> Result<Answer<T>, Error> {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NoError,
+            ..
+        }
+        | ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NXDomain,
+            ..
+        } => Ok(Answer::empty(negative_ttl(&error))),
+        _ => Err(error),
+    }
+}
+
+/// In-memory [`Lookup`] for tests, names without records do not exist.
+#[derive(Debug, Clone)]
+pub struct MemoryLookup {
+    a: HashMap<Name, Vec<Ipv4Addr>>,
+    txt: HashMap<Name, Vec<String>>,
+    errors: HashMap<Name, Error>,
+    ttl: Duration,
+}
+
+impl Default for MemoryLookup {
+    fn default() -> Self {
+        Self {
+            a: HashMap::new(),
+            txt: HashMap::new(),
+            errors: HashMap::new(),
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+impl MemoryLookup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// TTL reported for every answer.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn a(mut self, name: Name, address: Ipv4Addr) -> Self {
+        self.a.entry(name).or_default().push(address);
+        self
+    }
+
+    pub fn txt<S: Into<String>>(mut self, name: Name, text: S) -> Self {
+        self.txt.entry(name).or_default().push(text.into());
+        self
+    }
+
+    /// Makes every lookup of `name` fail with `error`.
+    pub fn error(mut self, name: Name, error: Error) -> Self {
+        self.errors.insert(name, error);
+        self
+    }
+
+    /// Lists `ip_addr` on `list` with the return code `code` and an optional TXT record.
+    pub fn list_ip<A: Into<IpAddr>>(
+        self,
+        list: &BlockList,
+        ip_addr: A,
+        code: Ipv4Addr,
+        txt: Option<&str>,
+    ) -> Self {
+        self.list_name(ip_query(list, ip_addr.into()), code, txt)
+    }
+
+    /// Lists `domain` on `list` with the return code `code` and an optional TXT record.
+    pub fn list_domain(
+        self,
+        list: &BlockList,
+        domain: &Domain,
+        code: Ipv4Addr,
+        txt: Option<&str>,
+    ) -> Self {
+        self.list_name(domain_query(list, domain), code, txt)
+    }
+
+    fn list_name(self, name: Name, code: Ipv4Addr, txt: Option<&str>) -> Self {
+        let lookup = self.a(name.clone(), code);
+        match txt {
+            Some(txt) => lookup.txt(name, txt),
+            None => lookup,
+        }
+    }
+
+    fn answer<T: Clone>(
+        &self,
+        records: &HashMap<Name, Vec<T>>,
+        name: &Name,
+    ) -> Result<Answer<T>, Error> {
+        if let Some(error) = self.errors.get(name) {
+            return Err(error.clone());
+        }
+        Ok(match records.get(name) {
+            Some(records) => Answer {
+                records: records.clone(),
+                ttl: Some(self.ttl),
+            },
+            None => Answer::empty(Some(self.ttl)),
+        })
+    }
+}
+
+impl Lookup for MemoryLookup {
+    fn lookup_a(&self, name: Name) -> BoxFuture<'_, Result<Answer<Ipv4Addr>, Error>> {
+        let answer = self.answer(&self.a, &name);
+        Box::pin(async move { answer })
+    }
+
+    fn lookup_txt(&self, name: Name) -> BoxFuture<'_, Result<Answer<String>, Error>> {
+        let answer = self.answer(&self.txt, &name);
+        Box::pin(async move { answer })
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// An IP network in CIDR notation, a bare address is a network of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid network {:?}", s))?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&prefix| prefix <= max)
+                .ok_or_else(|| format!("invalid prefix length in {:?}", s))?,
+            None => max,
+        };
+        Ok(Self { address, prefix })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Special purpose address ranges, which public lists have no data for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressClass {
+    /// RFC 1918 `10.0.0.0/8`, `172.16.0.0/12` and `192.168.0.0/16`.
+    Private,
+    /// RFC 6598 `100.64.0.0/10`, used for carrier-grade NAT.
+    SharedAddressSpace,
+    /// `127.0.0.0/8` and `::1`.
+    Loopback,
+    /// `169.254.0.0/16` and `fe80::/10`.
+    LinkLocal,
+    /// RFC 5737 `192.0.2.0/24`, `198.51.100.0/24`, `203.0.113.0/24` and RFC 3849
+    /// `2001:db8::/32`.
+    Documentation,
+    /// RFC 4193 `fc00::/7`.
+    UniqueLocal,
+    /// `0.0.0.0` and `::`.
+    Unspecified,
+    /// `224.0.0.0/4`, `255.255.255.255` and `ff00::/8`.
+    Multicast,
+    /// Other special-purpose ranges: `0.0.0.0/8`, `240.0.0.0/4`, `198.18.0.0/15`,
+    /// `192.0.0.0/24`, `100::/64` and `2001:2::/48`.
+    Reserved,
+}
+
+impl AddressClass {
+    pub const ALL: [AddressClass; 9] = [
+        AddressClass::Private,
+        AddressClass::SharedAddressSpace,
+        AddressClass::Loopback,
+        AddressClass::LinkLocal,
+        AddressClass::Documentation,
+        AddressClass::UniqueLocal,
+        AddressClass::Unspecified,
+        AddressClass::Multicast,
+        AddressClass::Reserved,
+    ];
+
+    /// The class of `ip`, IPv4-mapped IPv6 addresses are classified as IPv4 addresses.
+    pub fn of(ip: IpAddr) -> Option<Self> {
+        match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                if ip.is_private() {
+                    Some(AddressClass::Private)
+                } else if a == 100 && (64..128).contains(&b) {
+                    Some(AddressClass::SharedAddressSpace)
+                } else if ip.is_loopback() {
+                    Some(AddressClass::Loopback)
+                } else if ip.is_link_local() {
+                    Some(AddressClass::LinkLocal)
+                } else if matches!((a, b, c), (192, 0, 2) | (198, 51, 100) | (203, 0, 113)) {
+                    Some(AddressClass::Documentation)
+                } else if ip.is_unspecified() {
+                    Some(AddressClass::Unspecified)
+                } else if ip.is_multicast() || ip.is_broadcast() {
+                    Some(AddressClass::Multicast)
+                } else if a == 0
+                    || a >= 240
+                    || (a == 198 && b & 0xfe == 18)
+                    || (a, b, c) == (192, 0, 0)
+                {
+                    Some(AddressClass::Reserved)
+                } else {
+                    None
+                }
+            }
+            IpAddr::V6(ip) => {
+                if let Some(mapped) = ip.to_ipv4_mapped() {
+                    return Self::of(mapped.into());
+                }
+                let [first, second, third, fourth, ..] = ip.segments();
+                if ip.is_loopback() {
+                    Some(AddressClass::Loopback)
+                } else if first & 0xffc0 == 0xfe80 {
+                    Some(AddressClass::LinkLocal)
+                } else if first == 0x2001 && second == 0x0db8 {
+                    Some(AddressClass::Documentation)
+                } else if first & 0xfe00 == 0xfc00 {
+                    Some(AddressClass::UniqueLocal)
+                } else if ip.is_unspecified() {
+                    Some(AddressClass::Unspecified)
+                } else if ip.is_multicast() {
+                    Some(AddressClass::Multicast)
+                } else if (first, second, third, fourth) == (0x0100, 0, 0, 0)
+                    || (first, second, third) == (0x2001, 0x0002, 0)
+                {
+                    Some(AddressClass::Reserved)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Decides which addresses [`DNSBL::check_ip`](crate::DNSBL::check_ip) and
+/// [`DNSBL::check_ip_all`](crate::DNSBL::check_ip_all) send to lists. Skipped addresses are
+/// answered with [`BlockStatus::NotApplicable`](crate::BlockStatus::NotApplicable) without any
+/// query, so internal addresses are not leaked to third parties.
+///
+/// By default every [`AddressClass`] is skipped and IPv4-mapped IPv6 addresses are queried as
+/// IPv4 addresses. The RFC 5782 test points are queried unless
+/// [`test_points`](Self::test_points) is turned off,
+/// [`DNSBL::health_check`](crate::DNSBL::health_check) queries them regardless of the policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressPolicy {
+    skip: BTreeSet<AddressClass>,
+    map_ipv4: bool,
+    test_points: bool,
+}
+
+impl Default for AddressPolicy {
+    fn default() -> Self {
+        Self {
+            skip: AddressClass::ALL.iter().copied().collect(),
+            map_ipv4: true,
+            test_points: true,
+        }
+    }
+}
+
+impl AddressPolicy {
+    /// Queries every address as given.
+    pub fn query_all() -> Self {
+        Self {
+            skip: BTreeSet::new(),
+            map_ipv4: false,
+            test_points: true,
+        }
+    }
+
+    pub fn skip(mut self, class: AddressClass) -> Self {
+        self.skip.insert(class);
+        self
+    }
+
+    pub fn allow(mut self, class: AddressClass) -> Self {
+        self.skip.remove(&class);
+        self
+    }
+
+    /// Whether IPv4-mapped IPv6 addresses like `::ffff:192.0.2.1` are queried as IPv4 addresses.
+    pub fn map_ipv4(mut self, map_ipv4: bool) -> Self {
+        self.map_ipv4 = map_ipv4;
+        self
+    }
+
+    /// Whether the test points `127.0.0.1`, `127.0.0.2` and their IPv4-mapped forms are queried
+    /// even though loopback addresses are skipped, so clients can probe lists through
+    /// [`DNSBL::check_ip`](crate::DNSBL::check_ip).
+    pub fn test_points(mut self, test_points: bool) -> Self {
+        self.test_points = test_points;
+        self
+    }
+
+    /// The address that is queried for `ip`.
+    pub fn normalize(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V6(v6) if self.map_ipv4 => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            _ => ip,
+        }
+    }
+
+    /// Whether `ip` is sent to lists.
+    pub fn applies(&self, ip: IpAddr) -> bool {
+        if self.test_points && is_test_point(ip) {
+            return true;
+        }
+        match AddressClass::of(ip) {
+            Some(class) => !self.skip.contains(&class),
+            None => true,
+        }
+    }
+}
+
+/// Whether `ip` is one of the RFC 5782 IPv4 test points, or its IPv4-mapped form.
+pub(crate) fn is_test_point(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip,
+            None => return false,
+        },
+    };
+    ip == Ipv4Addr::new(127, 0, 0, 1) || ip == Ipv4Addr::new(127, 0, 0, 2)
+}
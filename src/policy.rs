@@ -0,0 +1,286 @@
+//! Postfix policy delegation server, see
+//! <http://www.postfix.org/SMTPD_POLICY_README.html>.
+//!
+//! ```text
+//! smtpd_recipient_restrictions = ..., check_policy_service inet:127.0.0.1:10040
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::iter::FromIterator;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::rhsbl::{email_domain, normalize_domain};
+use crate::{BlockList, BlockStatus, CheckResults, Domain, DNSBL};
+
+/// Longest accepted attribute line, including the line break.
+const MAX_LINE: usize = 4096;
+/// Most attributes accepted in one request.
+const MAX_ATTRIBUTES: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Reject,
+    Defer,
+    Dunno,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Ok(Action::Reject),
+            "defer" => Ok(Action::Defer),
+            "dunno" => Ok(Action::Dunno),
+            _ => Err(format!("unknown action {:?}", s)),
+        }
+    }
+}
+
+/// Which attributes are checked against which lists and how the outcome is answered.
+#[derive(Debug, Clone)]
+pub struct PolicyConfig {
+    /// Lists for the `client_address` attribute.
+    pub client_lists: Vec<BlockList>,
+    /// Lists for the domain in the `helo_name` attribute.
+    pub helo_lists: Vec<BlockList>,
+    /// Lists for the domain of the `sender` attribute.
+    pub sender_lists: Vec<BlockList>,
+    /// Action if any list reports a listing.
+    pub on_listed: Action,
+    /// Action if no list reports a listing but at least one could not be queried.
+    pub on_error: Action,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            client_lists: Vec::new(),
+            helo_lists: Vec::new(),
+            sender_lists: Vec::new(),
+            on_listed: Action::Reject,
+            on_error: Action::Dunno,
+        }
+    }
+}
+
+/// Attributes of one policy delegation request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyRequest(HashMap<String, String>);
+
+impl PolicyRequest {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .get(name)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn client_address(&self) -> Option<IpAddr> {
+        self.get("client_address")?.parse().ok()
+    }
+
+    pub fn helo_domain(&self) -> Option<Domain> {
+        let helo = self.get("helo_name")?;
+        normalize_domain(helo).ok()
+    }
+
+    pub fn sender_domain(&self) -> Option<Domain> {
+        normalize_domain(email_domain(self.get("sender")?)?).ok()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for PolicyRequest {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(attributes: I) -> Self {
+        Self(
+            attributes
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Answer sent back to Postfix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub action: Action,
+    pub text: Option<String>,
+}
+
+impl Reply {
+    fn new(action: Action, text: String) -> Self {
+        Self {
+            action,
+            text: Some(text).filter(|text| !text.is_empty() && action != Action::Dunno),
+        }
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Action::Reject => "REJECT",
+            Action::Defer => "DEFER",
+            Action::Dunno => return write!(f, "action=DUNNO"),
+        };
+        match &self.text {
+            Some(text) => {
+                let text: String = text.chars().filter(|c| !c.is_control()).collect();
+                write!(f, "action={} {}", action, text)
+            }
+            None => write!(f, "action={}", action),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PolicyServer {
+    dnsbl: Arc<DNSBL>,
+    config: Arc<PolicyConfig>,
+}
+
+impl PolicyServer {
+    pub fn new(dnsbl: DNSBL, config: PolicyConfig) -> Self {
+        Self {
+            dnsbl: Arc::new(dnsbl),
+            config: Arc::new(config),
+        }
+    }
+
+    pub async fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move { server.handle(stream).await });
+        }
+    }
+
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, listener: UnixListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move { server.handle(stream).await });
+        }
+    }
+
+    /// Answers policy requests on `stream` until Postfix closes the connection. Connections
+    /// sending overlong lines or too many attributes are dropped with an `InvalidData` error.
+    pub async fn handle<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let mut request = HashMap::new();
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let limit = (MAX_LINE + 1) as u64;
+            if (&mut stream).take(limit).read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            if line.len() > MAX_LINE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "policy request line too long",
+                ));
+            }
+
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            if !line.is_empty() {
+                if let Some((name, value)) = line.split_once('=') {
+                    if request.len() >= MAX_ATTRIBUTES && !request.contains_key(name) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "too many policy request attributes",
+                        ));
+                    }
+                    request.insert(name.to_owned(), value.to_owned());
+                }
+                continue;
+            }
+
+            let reply = self
+                .decide(&PolicyRequest(std::mem::take(&mut request)))
+                .await;
+            let stream = stream.get_mut();
+            stream
+                .write_all(format!("{}\n\n", reply).as_bytes())
+                .await?;
+            stream.flush().await?;
+        }
+    }
+
+    pub async fn decide(&self, request: &PolicyRequest) -> Reply {
+        let mut results = Vec::new();
+
+        if let Some(client) = request.client_address() {
+            if !self.config.client_lists.is_empty() {
+                let checked = self
+                    .dnsbl
+                    .check_ip_all(&self.config.client_lists, client)
+                    .await;
+                results.push((client.to_string(), checked));
+            }
+        }
+        if let Some(helo) = request.helo_domain() {
+            if !self.config.helo_lists.is_empty() {
+                let checked = self
+                    .dnsbl
+                    .check_rhsbl_all(&self.config.helo_lists, &helo)
+                    .await;
+                results.push((helo.to_string(), checked));
+            }
+        }
+        if let Some(sender) = request.sender_domain() {
+            if !self.config.sender_lists.is_empty() {
+                let checked = self
+                    .dnsbl
+                    .check_rhsbl_all(&self.config.sender_lists, &sender)
+                    .await;
+                results.push((sender.to_string(), checked));
+            }
+        }
+
+        self.reply(&results)
+    }
+
+    fn reply(&self, results: &[(String, CheckResults)]) -> Reply {
+        let listed = results.iter().find_map(|(target, checked)| {
+            checked
+                .iter()
+                .find(|result| result.status.is_blocked())
+                .map(|result| (target, result))
+        });
+        if let Some((target, result)) = listed {
+            let text = match &result.status {
+                BlockStatus::Blocked {
+                    message: Some(message),
+                    ..
+                } => message.clone(),
+                _ => format!("{} is listed by {}", target, result.list),
+            };
+            return Reply::new(self.config.on_listed, text);
+        }
+
+        let failed = results.iter().find_map(|(_, checked)| {
+            checked
+                .iter()
+                .find(|result| matches!(result.status, BlockStatus::Error { .. }))
+        });
+        if let Some(result) = failed {
+            let text = format!("Temporary failure querying {}", result.list);
+            return Reply::new(self.config.on_error, text);
+        }
+
+        Reply::new(Action::Dunno, String::new())
+    }
+}
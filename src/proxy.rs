@@ -0,0 +1,96 @@
+//! Aggregating proxy zone: one query fans out to every upstream list and is answered with a
+//! single A record whose low 24 bits tell which lists returned a listing.
+//!
+//! The first list sets `127.0.0.2`, the second `127.0.0.4`, the third `127.0.0.8` and so on, up
+//! to the 23rd list at `127.128.0.0`. Bit 0 stays clear as URIBL-style clients read `127.0.0.1`
+//! as a refused query. The TXT record joins the messages of all listing lists.
+
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use crate::network::is_test_point;
+use crate::rbldnsd::parse_reversed_name;
+use crate::{BlockList, BlockStatus, CheckResults, Domain, DNSBL};
+
+#[derive(Clone)]
+pub struct ProxyZone {
+    dnsbl: Arc<DNSBL>,
+    lists: Vec<BlockList>,
+}
+
+impl ProxyZone {
+    pub const MAX_LISTS: usize = 23;
+
+    /// Build `dnsbl` with a [`CacheConfig`](crate::CacheConfig) so repeated queries are
+    /// answered without contacting the upstream lists.
+    ///
+    /// # Panics
+    ///
+    /// If more than [`MAX_LISTS`](Self::MAX_LISTS) lists are given.
+    pub fn new(dnsbl: Arc<DNSBL>, lists: Vec<BlockList>) -> Self {
+        assert!(
+            lists.len() <= Self::MAX_LISTS,
+            "a proxy zone supports at most {} lists",
+            Self::MAX_LISTS
+        );
+        Self { dnsbl, lists }
+    }
+
+    pub fn lists(&self) -> &[BlockList] {
+        &self.lists
+    }
+
+    /// Answers a query name relative to the zone, see
+    /// [`LocalList::check_query`](crate::rbldnsd::LocalList::check_query). The RFC 5782 test
+    /// points are passed upstream whatever the address policy of the [`DNSBL`], so health checks
+    /// of the zone see the health of its lists.
+    pub async fn check_query(&self, query: &str) -> BlockStatus {
+        let results = match parse_reversed_name(query) {
+            Some(ip) if is_test_point(ip) => self.dnsbl.query_ip_all(&self.lists, ip).await,
+            Some(ip) => self.dnsbl.check_ip_all(&self.lists, ip).await,
+            None => match Domain::new(query) {
+                Ok(domain) => self.dnsbl.check_domain_all(&self.lists, &domain).await,
+                Err(_) => return BlockStatus::NotBlocked,
+            },
+        };
+        self.aggregate(&results)
+    }
+
+    /// Combines per-list results into one listing. Without any listing a failed list turns the
+    /// whole answer into an error, so clients do not mistake an outage for a clean result.
+    pub fn aggregate(&self, results: &CheckResults) -> BlockStatus {
+        let mut mask = 0u32;
+        let mut reasons = BTreeSet::new();
+        let mut messages = Vec::new();
+
+        for (bit, list) in (1..).zip(&self.lists) {
+            if let Some(BlockStatus::Blocked { message, .. }) =
+                results.get(list).map(|result| &result.status)
+            {
+                mask |= 1 << bit;
+                reasons.insert(list.to_string());
+                messages.push(match message {
+                    Some(message) => format!("{}: {}", list, message),
+                    None => list.to_string(),
+                });
+            }
+        }
+
+        if mask == 0 {
+            let error = results
+                .iter()
+                .find(|result| matches!(result.status, BlockStatus::Error { .. }));
+            return match error {
+                Some(result) => result.status.clone(),
+                None => BlockStatus::NotBlocked,
+            };
+        }
+
+        BlockStatus::Blocked {
+            addresses: vec![Ipv4Addr::from(0x7f00_0000 | mask)],
+            reasons,
+            message: Some(messages.join("; ")),
+        }
+    }
+}
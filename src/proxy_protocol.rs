@@ -0,0 +1,260 @@
+//! Reading the HAProxy PROXY protocol header, versions 1 and 2, see
+//! <https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt>.
+//!
+//! Load balancers send the header once at the start of every connection. It carries the
+//! address of the actual client, which is what should be checked against lists.
+//!
+//! [`ProxyAcceptor`] reads the header of every accepted connection and exposes the client
+//! address through axum's [`ConnectInfo`](axum::extract::ConnectInfo):
+//!
+//! ```ignore
+//! let acceptor = ProxyAcceptor::new(TcpListener::bind(addr).await?, Duration::from_secs(5));
+//! axum::Server::builder(acceptor)
+//!     .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+//!     .await?;
+//! ```
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::extract::connect_info::Connected;
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use hyper::server::accept::Accept;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107;
+/// Connections whose header is read at the same time, further connections wait in the backlog.
+const MAX_PENDING: usize = 128;
+
+/// Addresses announced by a PROXY protocol header. Both are `None` for health checks of the
+/// load balancer and for unknown protocols.
+///
+/// Insert this as a request extension to make [`BlockLayer`](crate::layer::BlockLayer) check
+/// `source` instead of the address of the load balancer, or serve through a [`ProxyAcceptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+    const LOCAL: Self = Self {
+        source: None,
+        destination: None,
+    };
+}
+
+/// Reads the header from the start of a connection, leaving the stream positioned at the first
+/// byte of the proxied data. Connections without a valid header fail with
+/// [`io::ErrorKind::InvalidData`].
+pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<ProxyHeader> {
+    // The shortest v1 header is `PROXY UNKNOWN\r\n`, so this never reads past either version.
+    let mut start = [0; 12];
+    reader.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(reader).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(reader, &start).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(reader: &mut R, start: &[u8]) -> io::Result<ProxyHeader> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY protocol header too long"));
+        }
+        line.push(reader.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY protocol header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(ProxyHeader::LOCAL),
+        ["PROXY", "TCP4", source, destination, source_port, destination_port]
+        | ["PROXY", "TCP6", source, destination, source_port, destination_port] => {
+            let address = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+                let ip = ip
+                    .parse::<IpAddr>()
+                    .map_err(|_| invalid("invalid address"))?;
+                let port = port.parse::<u16>().map_err(|_| invalid("invalid port"))?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            Ok(ProxyHeader {
+                source: Some(address(*source, *source_port)?),
+                destination: Some(address(*destination, *destination_port)?),
+            })
+        }
+        _ => Err(invalid("malformed PROXY protocol header")),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<ProxyHeader> {
+    let version_command = reader.read_u8().await?;
+    let family = reader.read_u8().await?;
+    let length = usize::from(reader.read_u16().await?);
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match version_command & 0x0f {
+        0 => return Ok(ProxyHeader::LOCAL),
+        1 => {}
+        _ => return Err(invalid("unsupported PROXY protocol command")),
+    }
+
+    let port = |offset: usize| u16::from_be_bytes([payload[offset], payload[offset + 1]]);
+    match family >> 4 {
+        1 if payload.len() >= 12 => {
+            let source = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let destination = Ipv4Addr::new(payload[4], payload[5], payload[6], payload[7]);
+            Ok(ProxyHeader {
+                source: Some(SocketAddr::new(source.into(), port(8))),
+                destination: Some(SocketAddr::new(destination.into(), port(10))),
+            })
+        }
+        2 if payload.len() >= 36 => {
+            let ip = |offset: usize| {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&payload[offset..offset + 16]);
+                IpAddr::from(Ipv6Addr::from(octets))
+            };
+            Ok(ProxyHeader {
+                source: Some(SocketAddr::new(ip(0), port(32))),
+                destination: Some(SocketAddr::new(ip(16), port(34))),
+            })
+        }
+        1 | 2 => Err(invalid("truncated PROXY protocol addresses")),
+        _ => Ok(ProxyHeader::LOCAL),
+    }
+}
+
+/// A connection whose PROXY protocol header has been read.
+#[derive(Debug)]
+pub struct ProxyStream<S> {
+    inner: S,
+    header: ProxyHeader,
+    peer: SocketAddr,
+}
+
+impl<S> ProxyStream<S> {
+    pub fn header(&self) -> ProxyHeader {
+        self.header
+    }
+
+    /// Address of the load balancer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Address of the client, or of the load balancer if the header does not tell.
+    pub fn client_addr(&self) -> SocketAddr {
+        self.header.source.unwrap_or(self.peer)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ProxyStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ProxyStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Makes [`ProxyStream::client_addr`] available as `ConnectInfo<SocketAddr>`.
+impl<S> Connected<&ProxyStream<S>> for SocketAddr {
+    fn connect_info(stream: &ProxyStream<S>) -> Self {
+        stream.client_addr()
+    }
+}
+
+/// Accepts TCP connections and reads their PROXY protocol header, for use with
+/// [`axum::Server::builder`]. Connections without a valid header within the timeout are
+/// closed.
+pub struct ProxyAcceptor {
+    connections: BoxStream<'static, ProxyStream<TcpStream>>,
+}
+
+impl ProxyAcceptor {
+    pub fn new(listener: TcpListener, timeout: Duration) -> Self {
+        let accepted = stream::unfold(listener, |listener| async move {
+            loop {
+                match listener.accept().await {
+                    Ok(accepted) => return Some((accepted, listener)),
+                    // Back off instead of spinning on errors like too many open files.
+                    Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+                }
+            }
+        });
+        let connections = accepted
+            .map(move |(mut stream, peer)| async move {
+                let header = tokio::time::timeout(timeout, read_header(&mut stream))
+                    .await
+                    .ok()?
+                    .ok()?;
+                Some(ProxyStream {
+                    inner: stream,
+                    header,
+                    peer,
+                })
+            })
+            .buffer_unordered(MAX_PENDING)
+            .filter_map(future::ready)
+            .boxed();
+        Self { connections }
+    }
+}
+
+impl Accept for ProxyAcceptor {
+    type Conn = ProxyStream<TcpStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.connections
+            .poll_next_unpin(cx)
+            .map(|connection| connection.map(Ok))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,647 @@
+//! Datasets in the rbldnsd format, evaluated locally without any DNS traffic.
+//!
+//! Supported dataset types are `ip4set`, `ip4tset`, `ip6trie`, `dnset` and `generic`. Entries
+//! may carry their own `:A:TXT` value, otherwise the last default value line (`:A:TXT`) applies.
+//! In TXT templates `$` is replaced with the queried address or domain and `$0` to `$9` with
+//! the substitution variables defined by `$0 text` lines. Where entries overlap the most
+//! specific one wins, so `!` exclusions can punch holes into larger listed ranges.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{BlockStatus, Domain, ReturnCodes};
+
+const DEFAULT_A: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatasetKind {
+    Ip4Set,
+    Ip4TSet,
+    Ip6Trie,
+    DnSet,
+    Generic,
+}
+
+impl FromStr for DatasetKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ip4set" => Ok(DatasetKind::Ip4Set),
+            "ip4tset" => Ok(DatasetKind::Ip4TSet),
+            "ip6trie" => Ok(DatasetKind::Ip6Trie),
+            "dnset" => Ok(DatasetKind::DnSet),
+            "generic" => Ok(DatasetKind::Generic),
+            _ => Err(format!("unsupported dataset type {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(error) => error.fmt(f),
+            DatasetError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl error::Error for DatasetError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DatasetError::Io(error) => Some(error),
+            DatasetError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for DatasetError {
+    fn from(error: io::Error) -> Self {
+        DatasetError::Io(error)
+    }
+}
+
+/// A/TXT pair of an entry, the TXT is a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Value {
+    a: Ipv4Addr,
+    txt: Option<String>,
+}
+
+impl Value {
+    fn parse(value: &str, default: &Value) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Some(default.clone());
+        }
+
+        let (a, txt) = match value.strip_prefix(':') {
+            Some(rest) => match rest.split_once(':') {
+                Some((a, txt)) => (a, Some(txt)),
+                None => (rest, None),
+            },
+            None => ("", Some(value)),
+        };
+
+        let a = match a.trim() {
+            "" => default.a,
+            a => match a.parse::<Ipv4Addr>() {
+                Ok(a) => a,
+                Err(_) => Ipv4Addr::new(127, 0, 0, a.parse().ok()?),
+            },
+        };
+        let txt = match txt.map(str::trim) {
+            Some("") => None,
+            Some(txt) => Some(txt.to_owned()),
+            None => default.txt.clone(),
+        };
+
+        Some(Self { a, txt })
+    }
+}
+
+/// Longest-prefix map for addresses of `bits` length, values index into `Dataset::values` and
+/// `None` marks an exclusion.
+#[derive(Debug, Clone, Default)]
+struct PrefixMap {
+    bits: u8,
+    prefixes: BTreeMap<u8, HashMap<u128, Option<usize>>>,
+}
+
+impl PrefixMap {
+    fn new(bits: u8) -> Self {
+        Self {
+            bits,
+            prefixes: BTreeMap::new(),
+        }
+    }
+
+    fn mask(&self, address: u128, len: u8) -> u128 {
+        match len {
+            0 => 0,
+            len => address & (u128::MAX << (self.bits - len)),
+        }
+    }
+
+    fn insert(&mut self, address: u128, len: u8, value: Option<usize>) {
+        let key = self.mask(address, len);
+        let entry = self
+            .prefixes
+            .entry(len)
+            .or_default()
+            .entry(key)
+            .or_insert(value);
+        if value.is_none() {
+            *entry = None;
+        }
+    }
+
+    fn get(&self, address: u128) -> Option<Option<usize>> {
+        self.prefixes
+            .iter()
+            .rev()
+            .find_map(|(&len, entries)| entries.get(&self.mask(address, len)).copied())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct DomainSet {
+    exact: HashMap<String, Option<usize>>,
+    subdomains: HashMap<String, Option<usize>>,
+}
+
+impl DomainSet {
+    fn get(&self, domain: &str) -> Option<Option<usize>> {
+        if let Some(&value) = self.exact.get(domain) {
+            return Some(value);
+        }
+
+        let mut parent = domain;
+        while let Some((_, rest)) = parent.split_once('.') {
+            if let Some(&value) = self.subdomains.get(rest) {
+                return Some(value);
+            }
+            parent = rest;
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Records {
+    a: Vec<Ipv4Addr>,
+    txt: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Data {
+    Ip4(PrefixMap),
+    Ip6(PrefixMap),
+    Domains(DomainSet),
+    Generic(HashMap<String, Records>),
+}
+
+/// One parsed rbldnsd data file.
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    kind: DatasetKind,
+    data: Data,
+    values: Vec<Value>,
+    variables: HashMap<u8, String>,
+    ttl: Option<u32>,
+}
+
+impl Dataset {
+    pub fn load<P: AsRef<Path>>(kind: DatasetKind, path: P) -> Result<Self, DatasetError> {
+        Self::parse(kind, &fs::read_to_string(path)?)
+    }
+
+    pub fn parse(kind: DatasetKind, data: &str) -> Result<Self, DatasetError> {
+        let mut dataset = Self {
+            kind,
+            data: match kind {
+                DatasetKind::Ip4Set | DatasetKind::Ip4TSet => Data::Ip4(PrefixMap::new(32)),
+                DatasetKind::Ip6Trie => Data::Ip6(PrefixMap::new(128)),
+                DatasetKind::DnSet => Data::Domains(DomainSet::default()),
+                DatasetKind::Generic => Data::Generic(HashMap::new()),
+            },
+            values: Vec::new(),
+            variables: HashMap::new(),
+            ttl: None,
+        };
+        let mut default = Value {
+            a: DEFAULT_A,
+            txt: None,
+        };
+
+        for (number, line) in data.lines().enumerate() {
+            let error = |message: &str| DatasetError::Parse {
+                line: number + 1,
+                message: format!("{}: {:?}", message, line),
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(special) = line.strip_prefix('$') {
+                dataset
+                    .parse_special(special)
+                    .ok_or_else(|| error("invalid special entry"))?;
+            } else if kind != DatasetKind::Generic && line.starts_with(':') {
+                default = Value::parse(line, &default).ok_or_else(|| error("invalid value"))?;
+            } else {
+                dataset
+                    .parse_entry(line, &default)
+                    .ok_or_else(|| error("invalid entry"))?;
+            }
+        }
+
+        Ok(dataset)
+    }
+
+    pub fn kind(&self) -> DatasetKind {
+        self.kind
+    }
+
+    /// TTL set with `$TTL`, if any.
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    fn parse_special(&mut self, special: &str) -> Option<()> {
+        let (name, value) = special
+            .split_once(char::is_whitespace)
+            .map(|(name, value)| (name, value.trim()))
+            .unwrap_or((special, ""));
+
+        match name.as_bytes() {
+            [digit @ b'0'..=b'9'] => {
+                self.variables.insert(digit - b'0', value.to_owned());
+            }
+            _ if name.eq_ignore_ascii_case("TTL") => self.ttl = Some(value.parse().ok()?),
+            // $SOA, $NS, $TIMESTAMP, $MAXRANGE4, $DATASET: only relevant when serving a zone
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn push_value(&mut self, value: &str, default: &Value) -> Option<usize> {
+        let value = Value::parse(value, default)?;
+        if self.values.last() != Some(&value) {
+            self.values.push(value);
+        }
+        Some(self.values.len() - 1)
+    }
+
+    fn parse_entry(&mut self, line: &str, default: &Value) -> Option<()> {
+        if self.kind == DatasetKind::Generic {
+            return self.parse_generic(line);
+        }
+
+        let (key, value) = match self.kind {
+            DatasetKind::Ip6Trie => line.split_once(char::is_whitespace).unwrap_or((line, "")),
+            _ => line
+                .find(|c: char| c.is_whitespace() || c == ':')
+                .map(|i| line.split_at(i))
+                .unwrap_or((line, "")),
+        };
+        let (excluded, key) = match key.strip_prefix('!') {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        let value = if excluded {
+            None
+        } else if self.kind == DatasetKind::Ip4TSet {
+            Some(self.push_value("", default)?)
+        } else {
+            Some(self.push_value(value, default)?)
+        };
+
+        match &mut self.data {
+            Data::Ip4(map) => {
+                let cidrs = parse_ip4(key)?;
+                if self.kind == DatasetKind::Ip4TSet && cidrs != [(cidrs[0].0, 32)] {
+                    return None;
+                }
+                for (address, len) in cidrs {
+                    map.insert(u128::from(address), len, value);
+                }
+            }
+            Data::Ip6(map) => {
+                let (address, len) = match key.split_once('/') {
+                    Some((address, len)) => (address, len.parse().ok()?),
+                    None => (key, 128),
+                };
+                if len > 128 {
+                    return None;
+                }
+                map.insert(u128::from(address.parse::<Ipv6Addr>().ok()?), len, value);
+            }
+            Data::Domains(set) => {
+                let key = key.trim_end_matches('.').to_ascii_lowercase();
+                if let Some(parent) = key.strip_prefix("*.") {
+                    set.subdomains.insert(parent.to_owned(), value);
+                } else if let Some(parent) = key.strip_prefix('.') {
+                    set.exact.insert(parent.to_owned(), value);
+                    set.subdomains.insert(parent.to_owned(), value);
+                } else if !key.is_empty() {
+                    set.exact.insert(key, value);
+                } else {
+                    return None;
+                }
+            }
+            Data::Generic(_) => unreachable!("handled above"),
+        }
+        Some(())
+    }
+
+    /// `name [ttl] type value`, only A and TXT records are kept.
+    fn parse_generic(&mut self, line: &str) -> Option<()> {
+        let records = match &mut self.data {
+            Data::Generic(records) => records,
+            _ => unreachable!("only called for generic datasets"),
+        };
+
+        let mut fields = line.splitn(2, char::is_whitespace);
+        let name = match fields.next()?.trim_end_matches('.').to_ascii_lowercase() {
+            name if name == "@" => String::new(),
+            name => name,
+        };
+        let mut rest = fields.next()?.trim_start();
+
+        let (first, remainder) = rest.split_once(char::is_whitespace)?;
+        if first.parse::<u32>().is_ok() {
+            rest = remainder.trim_start();
+        }
+        let (record_type, value) = rest
+            .split_once(char::is_whitespace)
+            .map(|(record_type, value)| (record_type, value.trim()))
+            .unwrap_or((rest, ""));
+
+        if record_type.eq_ignore_ascii_case("A") {
+            let a = value.parse().ok()?;
+            records.entry(name).or_default().a.push(a);
+        } else if record_type.eq_ignore_ascii_case("TXT") {
+            let txt = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            records.entry(name).or_default().txt.push(txt.to_owned());
+        }
+        Some(())
+    }
+
+    fn expand(&self, template: &str, subject: &str) -> String {
+        let mut expanded = String::with_capacity(template.len());
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.peek().and_then(|c| c.to_digit(10)) {
+                Some(digit) => {
+                    chars.next();
+                    if let Some(variable) = self.variables.get(&(digit as u8)) {
+                        expanded.push_str(variable);
+                    }
+                }
+                None => expanded.push_str(subject),
+            }
+        }
+        expanded
+    }
+
+    fn matched(&self, value: Option<Option<usize>>, subject: &str, matches: &mut Matches) {
+        if let Some(Some(index)) = value {
+            let value = &self.values[index];
+            matches.addresses.insert(value.a);
+            if let Some(txt) = &value.txt {
+                matches.txt.push(self.expand(txt, subject));
+            }
+        }
+    }
+
+    fn check_ip(&self, ip: IpAddr, matches: &mut Matches) {
+        let subject = ip.to_string();
+        match (&self.data, ip) {
+            (Data::Ip4(map), IpAddr::V4(ip)) => {
+                self.matched(map.get(u128::from(u32::from(ip))), &subject, matches)
+            }
+            (Data::Ip6(map), IpAddr::V6(ip)) => {
+                self.matched(map.get(u128::from(ip)), &subject, matches)
+            }
+            (Data::Generic(records), ip) => {
+                if let Some(records) = records.get(&reversed_name(ip)) {
+                    matches.generic(records, &subject, self);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_domain(&self, domain: &str, matches: &mut Matches) {
+        match &self.data {
+            Data::Domains(set) => self.matched(set.get(domain), domain, matches),
+            Data::Generic(records) => {
+                if let Some(records) = records.get(domain) {
+                    matches.generic(records, domain, self);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Matches {
+    addresses: BTreeSet<Ipv4Addr>,
+    txt: Vec<String>,
+}
+
+impl Matches {
+    fn generic(&mut self, records: &Records, subject: &str, dataset: &Dataset) {
+        self.addresses.extend(&records.a);
+        self.txt
+            .extend(records.txt.iter().map(|txt| dataset.expand(txt, subject)));
+    }
+}
+
+/// Query name of `ip` relative to the list, e.g. `4.3.2.1` for `1.2.3.4`.
+fn reversed_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{}.{}.{}.{}", d, c, b, a)
+        }
+        IpAddr::V6(ip) => {
+            let hex = format!("{:032x}", u128::from(ip));
+            let nibbles: Vec<String> = hex.chars().rev().map(String::from).collect();
+            nibbles.join(".")
+        }
+    }
+}
+
+pub(crate) fn parse_reversed_name(name: &str) -> Option<IpAddr> {
+    let labels: Vec<&str> = name.trim_end_matches('.').split('.').rev().collect();
+
+    match labels.len() {
+        4 => {
+            let mut octets = [0; 4];
+            for (octet, label) in octets.iter_mut().zip(&labels) {
+                *octet = label.parse().ok()?;
+            }
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        32 => {
+            let mut address = 0u128;
+            for label in labels {
+                let mut chars = label.chars();
+                let nibble = chars.next()?.to_digit(16)?;
+                if chars.next().is_some() {
+                    return None;
+                }
+                address = (address << 4) | u128::from(nibble);
+            }
+            Some(IpAddr::V6(Ipv6Addr::from(address)))
+        }
+        _ => None,
+    }
+}
+
+/// Parses `1.2.3.4`, `1.2.3`, `1.2.3.0/24`, `1.2.3.4-1.2.3.10` and `1.2.3.4-10` into CIDR
+/// blocks.
+fn parse_ip4(key: &str) -> Option<Vec<(u32, u8)>> {
+    if let Some((first, last)) = key.split_once('-') {
+        let (first, _) = parse_short_ip4(first)?;
+        let last = if last.contains('.') {
+            u32::from(last.parse::<Ipv4Addr>().ok()?)
+        } else {
+            (first & !0xff) | u32::from(last.parse::<u8>().ok()?)
+        };
+        if first > last {
+            return None;
+        }
+        return Some(range_to_cidrs(first, last));
+    }
+
+    match key.split_once('/') {
+        Some((address, len)) => {
+            let (address, _) = parse_short_ip4(address)?;
+            let len = len.parse().ok().filter(|&len| len <= 32)?;
+            Some(vec![(address, len)])
+        }
+        None => {
+            let (address, len) = parse_short_ip4(key)?;
+            Some(vec![(address, len)])
+        }
+    }
+}
+
+/// Parses an address with one to four octets, missing octets are zero and the implied prefix
+/// length covers the given octets.
+fn parse_short_ip4(address: &str) -> Option<(u32, u8)> {
+    let mut octets = [0; 4];
+    let mut count = 0;
+    for part in address.split('.') {
+        *octets.get_mut(count)? = part.parse().ok()?;
+        count += 1;
+    }
+    Some((u32::from_be_bytes(octets), (count * 8) as u8))
+}
+
+fn range_to_cidrs(first: u32, last: u32) -> Vec<(u32, u8)> {
+    let mut cidrs = Vec::new();
+    let (mut start, end) = (u64::from(first), u64::from(last));
+
+    while start <= end {
+        let mut size = if start == 0 {
+            32
+        } else {
+            start.trailing_zeros().min(32)
+        };
+        while start + (1 << size) - 1 > end {
+            size -= 1;
+        }
+        cidrs.push((start as u32, (32 - size) as u8));
+        start += 1 << size;
+    }
+    cidrs
+}
+
+/// A list backed by local rbldnsd datasets, answering like [`DNSBL`](crate::DNSBL) would for the
+/// same data served by rbldnsd.
+#[derive(Debug, Clone, Default)]
+pub struct LocalList {
+    datasets: Vec<Dataset>,
+    return_codes: Option<ReturnCodes>,
+}
+
+impl LocalList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dataset(mut self, dataset: Dataset) -> Self {
+        self.datasets.push(dataset);
+        self
+    }
+
+    /// Return-code table used to decode the answers into listing reasons.
+    pub fn return_codes(mut self, return_codes: ReturnCodes) -> Self {
+        self.return_codes = Some(return_codes);
+        self
+    }
+
+    pub fn datasets(&self) -> &[Dataset] {
+        &self.datasets
+    }
+
+    pub fn check_ip<A: Into<IpAddr>>(&self, ip_addr: A) -> BlockStatus {
+        let ip = ip_addr.into();
+        let mut matches = Matches::default();
+        for dataset in &self.datasets {
+            dataset.check_ip(ip, &mut matches);
+        }
+        self.status(matches)
+    }
+
+    pub fn check_domain(&self, domain: &Domain) -> BlockStatus {
+        let domain = domain.0.to_lowercase().to_ascii();
+        let domain = domain.trim_end_matches('.');
+        let mut matches = Matches::default();
+        for dataset in &self.datasets {
+            dataset.check_domain(domain, &mut matches);
+        }
+        self.status(matches)
+    }
+
+    /// Answers a query name relative to the list, as built by
+    /// [`DNSBL::check_ip`](crate::DNSBL::check_ip) and
+    /// [`DNSBL::check_domain`](crate::DNSBL::check_domain): reversed IPv4 octets, reversed IPv6
+    /// nibbles or a domain.
+    pub fn check_query(&self, query: &str) -> BlockStatus {
+        match parse_reversed_name(query) {
+            Some(ip) => self.check_ip(ip),
+            None => match Domain::new(query) {
+                Ok(domain) => self.check_domain(&domain),
+                Err(_) => BlockStatus::NotBlocked,
+            },
+        }
+    }
+
+    fn status(&self, matches: Matches) -> BlockStatus {
+        if matches.addresses.is_empty() {
+            return BlockStatus::NotBlocked;
+        }
+
+        let addresses: Vec<Ipv4Addr> = matches.addresses.into_iter().collect();
+        let reasons = self
+            .return_codes
+            .as_ref()
+            .map(|codes| codes.decode(&addresses))
+            .unwrap_or_default();
+        let message = Some(matches.txt.join(" ")).filter(|message| !message.is_empty());
+
+        BlockStatus::Blocked {
+            addresses,
+            reasons,
+            message,
+        }
+    }
+}
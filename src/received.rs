@@ -0,0 +1,249 @@
+//! The relays of an RFC 5322 message, taken from its `Received` header fields.
+//!
+//! The connecting client of each hop is read from the `from` clause in the formats written by
+//! Postfix, Sendmail, Exim, qmail, Exchange and Gmail, for example
+//!
+//! ```text
+//! from mail.example.com (mail.example.com [192.0.2.1]) by mx.example.net (Postfix) ...
+//! from mail.example.com ([192.0.2.1] helo=mail.example.com) by mx.example.net with esmtps ...
+//! from unknown (HELO mail.example.com) (192.0.2.1) by mx.example.net with SMTP ...
+//! ```
+
+use std::net::IpAddr;
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{AddressClass, BlockList, CheckResults, Network, DNSBL};
+
+/// One `Received` header field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hop {
+    /// Position of the field, 0 is the topmost and most recent hop.
+    pub index: usize,
+    /// The unfolded field value.
+    pub header: String,
+    /// Name the client announced with HELO or EHLO.
+    pub helo: Option<String>,
+    /// Address of the connecting client.
+    pub ip: Option<IpAddr>,
+    /// Host that received the message.
+    pub by: Option<String>,
+}
+
+impl Hop {
+    pub fn parse(index: usize, header: &str) -> Self {
+        let header = header.split_whitespace().collect::<Vec<_>>().join(" ");
+        let from = clause(&header, "from", &["by", "with", "id", "via", "for"]);
+        let by = clause(&header, "by", &["with", "id", "via", "for"])
+            .and_then(|by| by.split_whitespace().next())
+            .map(str::to_owned);
+
+        let (helo, ip) = match from {
+            Some(from) => (client_helo(from), client_ip(from)),
+            None => (None, None),
+        };
+
+        Self {
+            index,
+            helo,
+            ip,
+            by,
+            header,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedConfig {
+    /// Relays that are not checked, typically the own mail servers.
+    pub trusted: Vec<Network>,
+    /// Also check addresses of any [`AddressClass`], such as private and loopback addresses.
+    pub check_private: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", content = "results", rename_all = "snake_case")]
+pub enum HopStatus {
+    /// The client address could not be determined.
+    NoAddress,
+    /// The client address belongs to an [`AddressClass`].
+    Private,
+    Trusted,
+    Checked(CheckResults),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HopReport {
+    #[serde(flatten)]
+    pub hop: Hop,
+    #[serde(flatten)]
+    pub status: HopStatus,
+}
+
+/// The header block of `message`, everything before the first empty line.
+pub fn header_block(message: &str) -> &str {
+    let end = [message.find("\r\n\r\n"), message.find("\n\n")]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+    match end {
+        Some(end) => &message[..end],
+        None => message,
+    }
+}
+
+/// The unfolded header fields of `message` as name and value, in order.
+pub fn headers(message: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in header_block(message).lines() {
+        if line.starts_with(&[' ', '\t'][..]) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    fields
+}
+
+/// The hops of `message` from its `Received` header fields, most recent first.
+pub fn hops(message: &str) -> Vec<Hop> {
+    headers(message)
+        .into_iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("received"))
+        .enumerate()
+        .map(|(index, (_, value))| Hop::parse(index, &value))
+        .collect()
+}
+
+impl DNSBL {
+    /// Checks the client of every hop in `message` against `lists`, skipping trusted relays
+    /// and, unless configured otherwise, private addresses.
+    pub async fn check_received(
+        &self,
+        lists: &[BlockList],
+        message: &str,
+        config: &ReceivedConfig,
+    ) -> Vec<HopReport> {
+        stream::iter(hops(message))
+            .map(|hop| async move {
+                let status = match hop.ip {
+                    None => HopStatus::NoAddress,
+                    Some(ip) if config.trusted.iter().any(|network| network.contains(ip)) => {
+                        HopStatus::Trusted
+                    }
+                    Some(ip) if !config.check_private && AddressClass::of(ip).is_some() => {
+                        HopStatus::Private
+                    }
+                    Some(ip) => HopStatus::Checked(self.check_ip_all(lists, ip).await),
+                };
+                HopReport { hop, status }
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+}
+
+/// The text following the `keyword` clause up to the next of the `ends` clauses or `;`.
+fn clause<'a>(header: &'a str, keyword: &str, ends: &[&str]) -> Option<&'a str> {
+    let header = header.split(';').next().unwrap_or_default();
+    let lower = header.to_ascii_lowercase();
+    let start = if lower.starts_with(&format!("{} ", keyword)) {
+        keyword.len() + 1
+    } else {
+        lower.find(&format!(" {} ", keyword))? + keyword.len() + 2
+    };
+
+    // Clauses only end outside of comments, which often contain words like `with`.
+    let mut depth = 0usize;
+    let mut end = header.len();
+    for (offset, c) in header[start..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 => {
+                let rest = &lower[start + offset + 1..];
+                if ends
+                    .iter()
+                    .any(|next| rest.starts_with(&format!("{} ", next)) || rest == *next)
+                {
+                    end = start + offset;
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(header[start..end].trim())
+}
+
+fn client_helo(from: &str) -> Option<String> {
+    let words = words(from);
+    let announced = words.windows(2).find_map(|pair| match pair {
+        [marker, helo] if is_helo_marker(marker) => Some(*helo),
+        _ => None,
+    });
+    announced
+        .or_else(|| words.first().copied())
+        .map(|helo| helo.trim_end_matches('.').to_owned())
+}
+
+/// Prefers addresses in brackets within comments (Postfix, Sendmail, Exim, Gmail), then any
+/// bracketed address and finally bare addresses that are not a HELO argument (qmail, Exchange).
+fn client_ip(from: &str) -> Option<IpAddr> {
+    let mut bracketed = Vec::new();
+    let mut depth = 0usize;
+    for (offset, c) in from.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '[' => {
+                if let Some(end) = from[offset..].find(']') {
+                    bracketed.push((depth > 0, &from[offset + 1..offset + end]));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let in_comment = bracketed.iter().filter(|(comment, _)| *comment);
+    let anywhere = bracketed.iter().filter(|(comment, _)| !*comment);
+    let bracketed_ip = in_comment
+        .chain(anywhere)
+        .find_map(|(_, literal)| parse_ip(literal));
+    if bracketed_ip.is_some() {
+        return bracketed_ip;
+    }
+
+    let words = words(from);
+    words.iter().enumerate().find_map(|(i, word)| {
+        if i > 0 && is_helo_marker(words[i - 1]) {
+            return None;
+        }
+        parse_ip(word.rsplit('@').next().unwrap_or_default())
+    })
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| c.is_whitespace() || "()<>,;=".contains(c))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn is_helo_marker(word: &str) -> bool {
+    word.eq_ignore_ascii_case("helo") || word.eq_ignore_ascii_case("ehlo")
+}
+
+fn parse_ip(literal: &str) -> Option<IpAddr> {
+    let literal = literal.trim().trim_end_matches('.');
+    let literal = match literal.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("ipv6:") => &literal[5..],
+        _ => literal,
+    };
+    literal.parse().ok()
+}
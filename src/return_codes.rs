@@ -0,0 +1,186 @@
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+
+/// Maps the `127.0.0.x` answers of a list to human readable listing reasons.
+///
+/// Entries either match a range of addresses or, for lists that combine several sublists into
+/// one answer (SURBL, URIBL), any address that has one of the bits of a mask set.
+///
+/// Error entries mark answers a list gives instead of a listing, for example when it refuses
+/// queries from public resolvers. Such answers are reported as
+/// [`BlockStatus::Error`](crate::BlockStatus::Error) rather than as a listing.
+///
+/// Serialized as an array of entries:
+///
+/// ```json
+/// [
+///   {"first": "127.0.0.4", "last": "127.0.0.7", "reason": "XBL"},
+///   {"mask": 8, "reason": "PH"},
+///   {"first": "127.255.255.254", "last": "127.255.255.254", "reason": "public resolver", "error": true}
+/// ]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ReturnCodes {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    #[serde(flatten)]
+    matcher: Matcher,
+    reason: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    error: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Matcher {
+    Range { first: Ipv4Addr, last: Ipv4Addr },
+    Mask { mask: u32 },
+}
+
+impl Matcher {
+    fn matches(self, address: Ipv4Addr) -> bool {
+        match self {
+            Matcher::Range { first, last } => (first..=last).contains(&address),
+            Matcher::Mask { mask } => u32::from(address) & mask != 0,
+        }
+    }
+}
+
+impl ReturnCodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn code<A: Into<Ipv4Addr>, S: Into<String>>(self, code: A, reason: S) -> Self {
+        let code = code.into();
+        self.range(code, code, reason)
+    }
+
+    pub fn range<A: Into<Ipv4Addr>, S: Into<String>>(
+        mut self,
+        first: A,
+        last: A,
+        reason: S,
+    ) -> Self {
+        let matcher = Matcher::Range {
+            first: first.into(),
+            last: last.into(),
+        };
+        self.push(matcher, reason, false)
+    }
+
+    /// Matches every answer that has any of the bits in `mask` set.
+    pub fn mask<S: Into<String>>(self, mask: u32, reason: S) -> Self {
+        self.push(Matcher::Mask { mask }, reason, false)
+    }
+
+    /// Marks `code` as an error answer, see [`error_range`](Self::error_range).
+    pub fn error_code<A: Into<Ipv4Addr>, S: Into<String>>(self, code: A, reason: S) -> Self {
+        let code = code.into();
+        self.error_range(code, code, reason)
+    }
+
+    /// Marks the answers from `first` to `last` as the list refusing the query.
+    pub fn error_range<A: Into<Ipv4Addr>, S: Into<String>>(
+        self,
+        first: A,
+        last: A,
+        reason: S,
+    ) -> Self {
+        let matcher = Matcher::Range {
+            first: first.into(),
+            last: last.into(),
+        };
+        self.push(matcher, reason, true)
+    }
+
+    fn push<S: Into<String>>(mut self, matcher: Matcher, reason: S, error: bool) -> Self {
+        self.entries.push(Entry {
+            matcher,
+            reason: reason.into(),
+            error,
+        });
+        self
+    }
+
+    pub fn decode(&self, addresses: &[Ipv4Addr]) -> BTreeSet<String> {
+        self.matching(addresses)
+            .filter(|entry| !entry.error)
+            .map(|entry| entry.reason.clone())
+            .collect()
+    }
+
+    /// The reason of the first error entry matching any of `addresses`.
+    pub fn error(&self, addresses: &[Ipv4Addr]) -> Option<&str> {
+        self.matching(addresses)
+            .find(|entry| entry.error)
+            .map(|entry| entry.reason.as_str())
+    }
+
+    fn matching<'a>(&'a self, addresses: &'a [Ipv4Addr]) -> impl Iterator<Item = &'a Entry> {
+        self.entries.iter().filter(move |entry| {
+            addresses
+                .iter()
+                .any(|&address| entry.matcher.matches(address))
+        })
+    }
+
+    /// `zen.spamhaus.org`
+    pub fn spamhaus_zen() -> Self {
+        Self::new()
+            .code([127, 0, 0, 2], "SBL")
+            .code([127, 0, 0, 3], "CSS")
+            .range([127, 0, 0, 4], [127, 0, 0, 7], "XBL")
+            .code([127, 0, 0, 9], "DROP")
+            .range([127, 0, 0, 10], [127, 0, 0, 11], "PBL")
+            .spamhaus_errors()
+    }
+
+    /// `dbl.spamhaus.org`
+    pub fn spamhaus_dbl() -> Self {
+        Self::new()
+            .code([127, 0, 1, 2], "spam")
+            .code([127, 0, 1, 4], "phish")
+            .code([127, 0, 1, 5], "malware")
+            .code([127, 0, 1, 6], "botnet")
+            .code([127, 0, 1, 102], "abused-spam")
+            .code([127, 0, 1, 103], "abused-redirector")
+            .code([127, 0, 1, 104], "abused-phish")
+            .code([127, 0, 1, 105], "abused-malware")
+            .code([127, 0, 1, 106], "abused-botnet")
+            .error_code([127, 0, 1, 255], "IP queries are not supported")
+            .spamhaus_errors()
+    }
+
+    /// `multi.surbl.org`
+    pub fn surbl_multi() -> Self {
+        Self::new()
+            .mask(8, "PH")
+            .mask(16, "MW")
+            .mask(64, "ABUSE")
+            .mask(128, "CR")
+            .error_code([127, 0, 0, 1], "query refused")
+    }
+
+    /// `multi.uribl.com`
+    pub fn uribl_multi() -> Self {
+        Self::new()
+            .mask(2, "black")
+            .mask(4, "grey")
+            .mask(8, "red")
+            .error_code([127, 0, 0, 1], "query refused")
+    }
+
+    /// Answers of every Spamhaus zone for refused queries.
+    fn spamhaus_errors(self) -> Self {
+        self.error_code([127, 255, 255, 252], "typing error in the list name")
+            .error_code([127, 255, 255, 254], "query through a public resolver")
+            .error_code([127, 255, 255, 255], "excessive number of queries")
+    }
+}
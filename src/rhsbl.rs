@@ -0,0 +1,100 @@
+//! Domains for right-hand side lists (RHSBL) such as Spamhaus DBL, SURBL and URIBL, which list
+//! registrable domains like `example.co.uk` rather than every host name below them.
+//!
+//! Registrable domains are determined with the public suffix list snapshot bundled in the
+//! `psl` crate.
+
+use std::net::IpAddr;
+
+use futures::future;
+use serde::{Deserialize, Serialize};
+
+use crate::{BlockList, CheckResults, Domain, Error, DNSBL};
+
+/// Which names are queried for a domain, see [`DNSBLBuilder::domain_level`](crate::DNSBLBuilder::domain_level).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainLevel {
+    /// Only the registrable domain, `example.co.uk` for `mail.sub.example.co.uk`.
+    #[default]
+    Registrable,
+    /// Only the domain as given.
+    Full,
+    /// The registrable domain and, if it differs, the domain as given.
+    Both,
+}
+
+/// Lowercases `name`, converts internationalised labels to punycode and drops a trailing dot.
+/// Address literals are rejected.
+pub fn normalize_domain(name: &str) -> Result<Domain, Error> {
+    let name = name.trim().trim_end_matches('.');
+    if name.starts_with('[') || name.parse::<IpAddr>().is_ok() {
+        return Err(Error::from(format!("{:?} is an address literal", name)));
+    }
+    let ascii = idna::domain_to_ascii(name)
+        .map_err(|_| Error::from(format!("invalid domain {:?}", name)))?;
+    Domain::new(ascii)
+}
+
+/// The registrable domain of `domain`, `None` if `domain` is itself a public suffix.
+pub fn registrable_domain(domain: &Domain) -> Option<Domain> {
+    let name = domain.to_string().to_ascii_lowercase();
+    let registrable = psl::domain_str(name.trim_end_matches('.'))?;
+    Domain::new(registrable).ok()
+}
+
+/// The domain part of an email address, also accepting `Name <local@domain>`.
+pub fn email_domain(address: &str) -> Option<&str> {
+    let address = address.trim();
+    let address = match address.rfind('<') {
+        Some(start) => address[start + 1..].trim_end().trim_end_matches('>'),
+        None => address,
+    };
+    let (_, domain) = address.rsplit_once('@')?;
+    Some(domain.trim()).filter(|domain| !domain.is_empty())
+}
+
+impl DomainLevel {
+    fn queries(self, domain: &Domain) -> Vec<Domain> {
+        let registrable = registrable_domain(domain);
+        match (self, registrable) {
+            (DomainLevel::Registrable, Some(registrable)) => vec![registrable],
+            (DomainLevel::Both, Some(registrable)) if registrable != *domain => {
+                vec![registrable, domain.clone()]
+            }
+            _ => vec![domain.clone()],
+        }
+    }
+}
+
+impl DNSBL {
+    /// Checks `domain` against domain lists, querying the levels selected by
+    /// [`DNSBLBuilder::domain_level`](crate::DNSBLBuilder::domain_level). With both levels the
+    /// results of the registrable domain come first.
+    pub async fn check_rhsbl_all(&self, lists: &[BlockList], domain: &Domain) -> CheckResults {
+        let queries = self.domain_level.queries(domain);
+        let checks = queries
+            .iter()
+            .map(|query| self.check_domain_all(lists, query));
+        CheckResults(
+            future::join_all(checks)
+                .await
+                .into_iter()
+                .flatten()
+                .collect(),
+        )
+    }
+
+    /// Checks the domain of the email address `sender` against domain lists, see
+    /// [`check_rhsbl_all`](Self::check_rhsbl_all).
+    pub async fn check_email_sender(
+        &self,
+        lists: &[BlockList],
+        sender: &str,
+    ) -> Result<CheckResults, Error> {
+        let domain = email_domain(sender)
+            .ok_or_else(|| Error::from(format!("invalid email address {:?}", sender)))?;
+        let domain = normalize_domain(domain)?;
+        Ok(self.check_rhsbl_all(lists, &domain).await)
+    }
+}
@@ -0,0 +1,260 @@
+//! Weighted scoring across lists, modelled after Postfix postscreen's `postscreen_dnsbl_sites`:
+//!
+//! ```text
+//! zen.spamhaus.org=127.0.0.[2..11]*3 bl.spamcop.net*2 list.dnswl.org=127.0.[0..255].[1..3]*-5
+//! ```
+//!
+//! Every entry whose list answers with an address matching its filter (any address if no filter
+//! is given) adds its weight, which defaults to 1, to the total.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{BlockList, BlockStatus, ListResult, DNSBL};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Pattern for the addresses returned by a list, each octet is a set of inclusive ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressFilter([Vec<(u8, u8)>; 4]);
+
+impl AddressFilter {
+    pub fn matches(&self, address: Ipv4Addr) -> bool {
+        self.0
+            .iter()
+            .zip(&address.octets())
+            .all(|(ranges, &octet)| {
+                ranges
+                    .iter()
+                    .any(|&(first, last)| (first..=last).contains(&octet))
+            })
+    }
+}
+
+impl FromStr for AddressFilter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError(format!("invalid address filter {:?}", s));
+        let mut octets = Vec::new();
+        let mut rest = s;
+
+        loop {
+            let (ranges, remainder) = if let Some(inner) = rest.strip_prefix('[') {
+                let end = inner.find(']').ok_or_else(invalid)?;
+                let ranges = inner[..end]
+                    .split(';')
+                    .map(|range| parse_range(range).ok_or_else(invalid))
+                    .collect::<Result<Vec<_>, _>>()?;
+                (ranges, &inner[end + 1..])
+            } else {
+                let end = rest.find('.').unwrap_or(rest.len());
+                let octet = rest[..end].parse().map_err(|_| invalid())?;
+                (vec![(octet, octet)], &rest[end..])
+            };
+            octets.push(ranges);
+
+            if remainder.is_empty() {
+                break;
+            }
+            rest = remainder.strip_prefix('.').ok_or_else(invalid)?;
+        }
+
+        let octets: [Vec<(u8, u8)>; 4] = octets.try_into().map_err(|_| invalid())?;
+        Ok(Self(octets))
+    }
+}
+
+fn parse_range(range: &str) -> Option<(u8, u8)> {
+    match range.split_once("..") {
+        Some((first, last)) => {
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            Some((first, last)).filter(|_| first <= last)
+        }
+        None => range.parse().ok().map(|octet| (octet, octet)),
+    }
+}
+
+impl fmt::Display for AddressFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, ranges) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            match ranges.as_slice() {
+                [(first, last)] if first == last => write!(f, "{}", first)?,
+                _ => {
+                    let ranges = ranges
+                        .iter()
+                        .map(|(first, last)| {
+                            if first == last {
+                                first.to_string()
+                            } else {
+                                format!("{}..{}", first, last)
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    write!(f, "[{}]", ranges.join(";"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One `list[=filter][*weight]` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoreEntry {
+    pub list: BlockList,
+    pub filter: Option<AddressFilter>,
+    /// Added to the score on a match, negative for allowlists.
+    pub weight: i32,
+}
+
+impl ScoreEntry {
+    /// Parses a whitespace or comma separated list of entries.
+    pub fn parse_all(s: &str) -> Result<Vec<Self>, ParseError> {
+        s.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    pub fn matches(&self, status: &BlockStatus) -> bool {
+        match status {
+            BlockStatus::Blocked { addresses, .. } => match &self.filter {
+                Some(filter) => addresses.iter().any(|&address| filter.matches(address)),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ScoreEntry {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rest, weight) = match s.rsplit_once('*') {
+            Some((rest, weight)) => {
+                let weight = weight
+                    .parse()
+                    .map_err(|_| ParseError(format!("invalid weight in {:?}", s)))?;
+                (rest, weight)
+            }
+            None => (s, 1),
+        };
+        let (list, filter) = match rest.split_once('=') {
+            Some((list, filter)) => (list, Some(filter.parse()?)),
+            None => (rest, None),
+        };
+        let list = list
+            .parse::<BlockList>()
+            .map_err(|error| ParseError(error.to_string()))?;
+
+        Ok(Self {
+            list,
+            filter,
+            weight,
+        })
+    }
+}
+
+impl fmt::Display for ScoreEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.list)?;
+        if let Some(filter) = &self.filter {
+            write!(f, "={}", filter)?;
+        }
+        if self.weight != 1 {
+            write!(f, "*{}", self.weight)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for ScoreEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScoreEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string: String = String::deserialize(deserializer)?;
+        string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Score {
+    pub total: i32,
+    /// One item per entry, in the order the entries were given.
+    pub breakdown: Vec<EntryScore>,
+}
+
+impl Score {
+    /// Whether the total reaches `threshold`, like `postscreen_dnsbl_threshold`.
+    pub fn reaches(&self, threshold: i32) -> bool {
+        self.total >= threshold
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryScore {
+    pub entry: ScoreEntry,
+    /// The weight of the entry if it matched, 0 otherwise.
+    pub score: i32,
+    pub result: ListResult,
+}
+
+impl DNSBL {
+    /// Queries every list of `entries` once and sums up the weights of the matching entries.
+    pub async fn score<A: Into<IpAddr>>(&self, entries: &[ScoreEntry], ip_addr: A) -> Score {
+        let mut seen = HashSet::new();
+        let lists: Vec<BlockList> = entries
+            .iter()
+            .filter(|entry| seen.insert(&entry.list))
+            .map(|entry| entry.list.clone())
+            .collect();
+
+        let results = self.check_ip_all(&lists, ip_addr).await;
+
+        let breakdown: Vec<EntryScore> = entries
+            .iter()
+            .filter_map(|entry| {
+                let result = results.get(&entry.list)?.clone();
+                let score = if entry.matches(&result.status) {
+                    entry.weight
+                } else {
+                    0
+                };
+                Some(EntryScore {
+                    entry: entry.clone(),
+                    score,
+                    result,
+                })
+            })
+            .collect();
+
+        Score {
+            total: breakdown.iter().map(|item| item.score).sum(),
+            breakdown,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Authoritative DNSBL server for zones backed by rbldnsd datasets, [`LocalList`]s supplied
+//! through the API or upstream lists aggregated by a [`ProxyZone`].
+//!
+//! Queries are answered for the same names [`DNSBL::check_ip`](crate::DNSBL::check_ip) and
+//! [`DNSBL::check_domain`](crate::DNSBL::check_domain) look up: reversed IPv4 octets, reversed
+//! IPv6 nibbles and domains below the zone.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Semaphore;
+use trust_dns_resolver::proto::{
+    op::{Query, ResponseCode},
+    rr::{RData, Record, RecordType},
+};
+
+use crate::proxy::ProxyZone;
+use crate::rbldnsd::{Dataset, DatasetError, DatasetKind, LocalList};
+use crate::wire::{self, Handler, Reply};
+use crate::{BlockStatus, Domain, Name};
+
+const DEFAULT_TTL: u32 = 2100;
+
+#[derive(Debug, Clone)]
+struct ZoneFile {
+    kind: DatasetKind,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ZoneFile {
+    fn modified(&self) -> Option<SystemTime> {
+        self.path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+enum Source {
+    Local {
+        list: LocalList,
+        files: Vec<ZoneFile>,
+    },
+    Proxy(ProxyZone),
+}
+
+struct Zone {
+    origin: Name,
+    ttl: u32,
+    source: Source,
+}
+
+impl Zone {
+    fn local(origin: Name, list: LocalList, files: Vec<ZoneFile>) -> Self {
+        let ttl = list
+            .datasets()
+            .iter()
+            .find_map(Dataset::ttl)
+            .unwrap_or(DEFAULT_TTL);
+        Self {
+            origin,
+            ttl,
+            source: Source::Local { list, files },
+        }
+    }
+
+    fn load(origin: Name, files: Vec<ZoneFile>) -> Result<Self, DatasetError> {
+        let mut list = LocalList::new();
+        let mut loaded = Vec::with_capacity(files.len());
+        for file in files {
+            let modified = file.modified();
+            list = list.dataset(Dataset::load(file.kind, &file.path)?);
+            loaded.push(ZoneFile { modified, ..file });
+        }
+        Ok(Self::local(origin, list, loaded))
+    }
+
+    fn files(&self) -> &[ZoneFile] {
+        match &self.source {
+            Source::Local { files, .. } => files.as_slice(),
+            Source::Proxy(_) => &[],
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.files()
+            .iter()
+            .any(|file| file.modified() != file.modified)
+    }
+
+    async fn answer(&self, query: &Query) -> Reply {
+        let name = query.name();
+        let relative = usize::from(name.num_labels() - self.origin.num_labels());
+
+        if relative == 0 {
+            let mut reply = Reply::new(ResponseCode::NoError);
+            let soa = wire::soa(&self.origin, self.ttl);
+            if matches!(query.query_type(), RecordType::SOA | RecordType::ANY) {
+                reply.answers.push(soa);
+            } else {
+                reply.authority.push(soa);
+            }
+            return reply;
+        }
+
+        let relative = name
+            .iter()
+            .take(relative)
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(".");
+
+        let status = match &self.source {
+            Source::Local { list, .. } => list.check_query(&relative),
+            Source::Proxy(proxy) => proxy.check_query(&relative).await,
+        };
+        let (addresses, message) = match status {
+            BlockStatus::Blocked {
+                addresses, message, ..
+            } => (addresses, message),
+            BlockStatus::NotBlocked | BlockStatus::NotApplicable => {
+                let mut reply = Reply::new(ResponseCode::NXDomain);
+                reply.authority.push(wire::soa(&self.origin, self.ttl));
+                return reply;
+            }
+            BlockStatus::Error { .. } => return Reply::new(ResponseCode::ServFail),
+        };
+
+        let mut reply = Reply::new(ResponseCode::NoError);
+        if matches!(query.query_type(), RecordType::A | RecordType::ANY) {
+            reply.answers.extend(
+                addresses
+                    .into_iter()
+                    .map(|a| Record::from_rdata(name.clone(), self.ttl, RData::A(a))),
+            );
+        }
+        if matches!(query.query_type(), RecordType::TXT | RecordType::ANY) {
+            reply.answers.extend(
+                message.map(|txt| Record::from_rdata(name.clone(), self.ttl, wire::txt(&txt))),
+            );
+        }
+        if reply.answers.is_empty() {
+            reply.authority.push(wire::soa(&self.origin, self.ttl));
+        }
+        reply
+    }
+}
+
+/// Serves any number of zones, which can be replaced or reloaded while serving.
+#[derive(Clone)]
+pub struct Server {
+    zones: Arc<RwLock<Vec<Arc<Zone>>>>,
+    max_pending: usize,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            zones: Arc::default(),
+            max_pending: wire::MAX_PENDING,
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of UDP queries and TCP connections handled at the same time, 1024 by
+    /// default. Proxy zones query every upstream list for each query, so further UDP queries
+    /// are refused and further connections wait in the listen backlog of the kernel.
+    pub fn max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+
+    /// Serves `list` as `origin`, replacing any previous zone of that name.
+    pub fn set_zone(&self, origin: &Domain, list: LocalList) {
+        self.insert(Zone::local(origin_name(origin), list, Vec::new()));
+    }
+
+    /// Serves `proxy` as `origin`, answering with a TTL of `ttl` seconds.
+    pub fn set_proxy_zone(&self, origin: &Domain, proxy: ProxyZone, ttl: u32) {
+        self.insert(Zone {
+            origin: origin_name(origin),
+            ttl,
+            source: Source::Proxy(proxy),
+        });
+    }
+
+    /// Serves the rbldnsd data files as `origin`. The files are reloaded by
+    /// [`reload`](Self::reload) once their modification time changes.
+    pub fn load_zone<P: Into<PathBuf>>(
+        &self,
+        origin: &Domain,
+        files: Vec<(DatasetKind, P)>,
+    ) -> Result<(), DatasetError> {
+        let files = files
+            .into_iter()
+            .map(|(kind, path)| ZoneFile {
+                kind,
+                path: path.into(),
+                modified: None,
+            })
+            .collect();
+        self.insert(Zone::load(origin_name(origin), files)?);
+        Ok(())
+    }
+
+    pub fn remove_zone(&self, origin: &Domain) {
+        let origin = origin_name(origin);
+        self.zones
+            .write()
+            .expect("zones lock poisoned")
+            .retain(|zone| zone.origin != origin);
+    }
+
+    /// Reloads every zone with changed data files. Zones that fail to load keep serving their
+    /// previous data, their errors are returned.
+    pub fn reload(&self) -> Vec<(Domain, DatasetError)> {
+        let stale: Vec<Arc<Zone>> = self
+            .zones
+            .read()
+            .expect("zones lock poisoned")
+            .iter()
+            .filter(|zone| zone.is_stale())
+            .cloned()
+            .collect();
+
+        let mut errors = Vec::new();
+        for zone in stale {
+            match Zone::load(zone.origin.clone(), zone.files().to_vec()) {
+                Ok(zone) => self.insert(zone),
+                Err(error) => errors.push((Domain(zone.origin.clone()), error)),
+            }
+        }
+        errors
+    }
+
+    /// Calls [`reload`](Self::reload) every `interval`, passing errors to `on_error`.
+    pub async fn watch<F: FnMut(Domain, DatasetError)>(&self, interval: Duration, mut on_error: F) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            for (origin, error) in self.reload() {
+                on_error(origin, error);
+            }
+        }
+    }
+
+    pub async fn serve(&self, udp: UdpSocket, tcp: TcpListener) -> io::Result<()> {
+        let handler: Arc<dyn Handler> = Arc::new(self.clone());
+        let pending = Arc::new(Semaphore::new(self.max_pending));
+        futures::try_join!(
+            wire::serve_udp(udp, handler.clone(), pending.clone()),
+            wire::serve_tcp(tcp, handler, pending)
+        )?;
+        Ok(())
+    }
+
+    fn insert(&self, zone: Zone) {
+        let mut zones = self.zones.write().expect("zones lock poisoned");
+        zones.retain(|existing| existing.origin != zone.origin);
+        zones.push(Arc::new(zone));
+    }
+
+    fn zone(&self, name: &Name) -> Option<Arc<Zone>> {
+        self.zones
+            .read()
+            .expect("zones lock poisoned")
+            .iter()
+            .filter(|zone| zone.origin.zone_of(name))
+            .max_by_key(|zone| zone.origin.num_labels())
+            .cloned()
+    }
+}
+
+impl Handler for Server {
+    fn answer<'a>(&'a self, query: &'a Query) -> BoxFuture<'a, Reply> {
+        let zone = self.zone(query.name());
+        async move {
+            match zone {
+                Some(zone) => zone.answer(query).await,
+                None => Reply::new(ResponseCode::Refused),
+            }
+        }
+        .boxed()
+    }
+}
+
+fn origin_name(origin: &Domain) -> Name {
+    Name::from_labels(&origin.0).expect("always valid")
+}
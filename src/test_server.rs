@@ -0,0 +1,203 @@
+//! Local DNSBL server for tests, serving fixed zones on `127.0.0.1` over UDP and TCP.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use dnsbl::test_server::{TestServer, TestZone};
+//! use dnsbl::BlockList;
+//!
+//! let list: BlockList = "bl.test".parse().unwrap();
+//! let zone = TestZone::new(&list).list_ip([192, 0, 2, 1], [127, 0, 0, 2], Some("listed"));
+//! let server = TestServer::start(vec![zone]).await?;
+//! let dnsbl = server.builder().build().unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use trust_dns_resolver::proto::{
+    op::{Query, ResponseCode},
+    rr::{RData, Record, RecordType},
+};
+
+use crate::wire::{self, Handler, Reply};
+use crate::{domain_query, ip_query, BlockList, DNSBLBuilder, Domain, Name, DNSBL};
+
+#[derive(Debug, Default, Clone)]
+struct Entry {
+    a: Vec<Ipv4Addr>,
+    txt: Vec<String>,
+}
+
+/// Contents of one list served by a [`TestServer`].
+#[derive(Debug, Clone)]
+pub struct TestZone {
+    origin: Name,
+    entries: HashMap<Name, Entry>,
+    response_code: Option<ResponseCode>,
+    ttl: u32,
+}
+
+impl TestZone {
+    pub fn new(list: &BlockList) -> Self {
+        Self {
+            origin: Name::from_labels(&list.domain.0).expect("always valid"),
+            entries: HashMap::new(),
+            response_code: None,
+            ttl: 300,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn list_ip<A: Into<IpAddr>, C: Into<Ipv4Addr>>(
+        self,
+        ip_addr: A,
+        code: C,
+        txt: Option<&str>,
+    ) -> Self {
+        let name = ip_query(&self.list(), ip_addr.into());
+        self.list_name(name, code.into(), txt)
+    }
+
+    pub fn list_domain<C: Into<Ipv4Addr>>(
+        self,
+        domain: &Domain,
+        code: C,
+        txt: Option<&str>,
+    ) -> Self {
+        let name = domain_query(&self.list(), domain);
+        self.list_name(name, code.into(), txt)
+    }
+
+    /// Answers every query for this zone with `response_code`, e.g. `SERVFAIL`.
+    pub fn fail(mut self, response_code: ResponseCode) -> Self {
+        self.response_code = Some(response_code);
+        self
+    }
+
+    fn list(&self) -> BlockList {
+        BlockList::new(Domain(self.origin.clone()))
+    }
+
+    fn list_name(mut self, name: Name, code: Ipv4Addr, txt: Option<&str>) -> Self {
+        let entry = self.entries.entry(name).or_default();
+        entry.a.push(code);
+        entry.txt.extend(txt.map(str::to_owned));
+        self
+    }
+
+    fn answer(&self, query: &Query) -> Reply {
+        if let Some(response_code) = self.response_code {
+            return Reply::new(response_code);
+        }
+
+        let name = query.name();
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => {
+                let mut reply = Reply::new(ResponseCode::NXDomain);
+                reply.authority.push(wire::soa(&self.origin, self.ttl));
+                return reply;
+            }
+        };
+
+        let mut reply = Reply::new(ResponseCode::NoError);
+        if matches!(query.query_type(), RecordType::A | RecordType::ANY) {
+            reply.answers.extend(
+                entry
+                    .a
+                    .iter()
+                    .map(|&a| Record::from_rdata(name.clone(), self.ttl, RData::A(a))),
+            );
+        }
+        if matches!(query.query_type(), RecordType::TXT | RecordType::ANY) {
+            reply.answers.extend(
+                entry
+                    .txt
+                    .iter()
+                    .map(|txt| Record::from_rdata(name.clone(), self.ttl, wire::txt(txt))),
+            );
+        }
+        if reply.answers.is_empty() {
+            reply.authority.push(wire::soa(&self.origin, self.ttl));
+        }
+        reply
+    }
+}
+
+struct Zones(Vec<TestZone>);
+
+impl Handler for Zones {
+    fn answer<'a>(&'a self, query: &'a Query) -> BoxFuture<'a, Reply> {
+        let reply = match self.0.iter().find(|zone| zone.origin.zone_of(query.name())) {
+            Some(zone) => zone.answer(query),
+            None => Reply::new(ResponseCode::Refused),
+        };
+        future::ready(reply).boxed()
+    }
+}
+
+/// DNS server on `127.0.0.1`, listening on the same port for UDP and TCP. The server stops when
+/// it is dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    tasks: Vec<JoinHandle<io::Result<()>>>,
+}
+
+impl TestServer {
+    pub async fn start(zones: Vec<TestZone>) -> io::Result<Self> {
+        let (udp, tcp) = bind().await?;
+        let addr = udp.local_addr()?;
+        let handler: Arc<dyn Handler> = Arc::new(Zones(zones));
+        let pending = Arc::new(Semaphore::new(wire::MAX_PENDING));
+
+        let tasks = vec![
+            tokio::spawn(wire::serve_udp(udp, handler.clone(), pending.clone())),
+            tokio::spawn(wire::serve_tcp(tcp, handler, pending)),
+        ];
+
+        Ok(Self { addr, tasks })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A builder for a [`DNSBL`] that queries this server.
+    pub fn builder(&self) -> DNSBLBuilder {
+        DNSBL::builder().name_servers(&[self.addr.ip()], self.addr.port())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn bind() -> io::Result<(UdpSocket, TcpListener)> {
+    let mut last_error = None;
+
+    for _ in 0..10 {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        match TcpListener::bind(udp.local_addr()?).await {
+            Ok(tcp) => return Ok((udp, tcp)),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.expect("at least one attempt"))
+}
@@ -0,0 +1,316 @@
+//! Extraction of the hosts in URLs and free text for URI lists such as SURBL, URIBL and
+//! Spamhaus DBL.
+//!
+//! Besides plain URLs this recognises
+//!
+//! - bare host names with a known public suffix, including the domains of email addresses,
+//! - obfuscated forms like `hxxp://example[.]com`,
+//! - HTML character references and percent-encoding,
+//! - IP literal hosts like `http://192.0.2.1/`, `http://[2001:db8::1]/` and the decimal and
+//!   hexadecimal forms `http://3221225985/`, `http://0xc0000201/`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::rhsbl::{normalize_domain, registrable_domain};
+use crate::{BlockList, CheckResults, Domain, Error, DNSBL};
+
+const SCHEMES: [&str; 3] = ["http", "https", "ftp"];
+
+/// Obfuscations and their replacements, matched case-insensitively.
+const OBFUSCATIONS: [(&str, &str); 9] = [
+    ("hxxp", "http"),
+    ("[://]", "://"),
+    ("[:]", ":"),
+    ("[.]", "."),
+    ("(.)", "."),
+    ("{.}", "."),
+    ("[dot]", "."),
+    ("(dot)", "."),
+    ("[at]", "@"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Host {
+    Ip(IpAddr),
+    Domain(Domain),
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Ip(ip) => ip.fmt(f),
+            Host::Domain(domain) => domain.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostResult {
+    pub host: Host,
+    pub results: CheckResults,
+}
+
+/// The host of `url`, which may be obfuscated or lack the scheme.
+pub fn url_host(url: &str) -> Option<Host> {
+    let url = deobfuscate(&decode_entities(url.trim()));
+    let url = percent_decode(&url);
+    match url.find("://") {
+        Some(start) => authority_host(&url[start + 3..]),
+        None => authority_host(&url),
+    }
+}
+
+/// Every distinct host in `text`, in order of first appearance.
+pub fn extract_hosts(text: &str) -> Vec<Host> {
+    let text = deobfuscate(&decode_entities(text));
+    let mut seen = HashSet::new();
+    let mut hosts = Vec::new();
+
+    for token in tokens(&text) {
+        let decoded = percent_decode(token);
+        for token in tokens(&decoded) {
+            if let Some(host) = token_host(token) {
+                if seen.insert(host.clone()) {
+                    hosts.push(host);
+                }
+            }
+        }
+    }
+
+    hosts
+}
+
+impl DNSBL {
+    /// Checks domains against `domain_lists`, see [`check_rhsbl_all`](Self::check_rhsbl_all),
+    /// and IP addresses against `ip_lists`.
+    pub async fn check_host(
+        &self,
+        domain_lists: &[BlockList],
+        ip_lists: &[BlockList],
+        host: &Host,
+    ) -> CheckResults {
+        match host {
+            Host::Ip(ip) => self.check_ip_all(ip_lists, *ip).await,
+            Host::Domain(domain) => self.check_rhsbl_all(domain_lists, domain).await,
+        }
+    }
+
+    /// Checks the host of `url`, see [`check_host`](Self::check_host).
+    pub async fn check_url(
+        &self,
+        domain_lists: &[BlockList],
+        ip_lists: &[BlockList],
+        url: &str,
+    ) -> Result<HostResult, Error> {
+        let host = url_host(url).ok_or_else(|| Error::from(format!("no host in {:?}", url)))?;
+        let results = self.check_host(domain_lists, ip_lists, &host).await;
+        Ok(HostResult { host, results })
+    }
+
+    /// Checks every host found in `text`, see [`extract_hosts`] and
+    /// [`check_host`](Self::check_host).
+    pub async fn check_text(
+        &self,
+        domain_lists: &[BlockList],
+        ip_lists: &[BlockList],
+        text: &str,
+    ) -> Vec<HostResult> {
+        stream::iter(extract_hosts(text))
+            .map(|host| async move {
+                let results = self.check_host(domain_lists, ip_lists, &host).await;
+                HostResult { host, results }
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+}
+
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c.is_whitespace() || c.is_control() || "\"'<>(){},;|\\^`=&*!".contains(c))
+        .filter(|token| !token.is_empty())
+}
+
+fn token_host(token: &str) -> Option<Host> {
+    if let Some(start) = token.find("://") {
+        let scheme = &token[..start];
+        let scheme = &scheme[scheme
+            .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+            .len()..];
+        if !SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) {
+            return None;
+        }
+        return authority_host(&token[start + 3..]);
+    }
+
+    // Bare names, only accepted with a known public suffix to skip file names and the like.
+    let token = token.trim_matches(&['[', ']'][..]);
+    let token = token.strip_prefix("mailto:").unwrap_or(token);
+    let name = token.rsplit('@').next()?;
+    let name = name.split(&['/', '?', '#', ':'][..]).next()?;
+    let name = name.trim_matches('.');
+    if !name.contains('.')
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-._".contains(c))
+    {
+        return None;
+    }
+    let domain = normalize_domain(name).ok()?;
+    let known = psl::suffix(domain.to_string().trim_end_matches('.').as_bytes())
+        .is_some_and(|suffix| suffix.is_known());
+    if known && registrable_domain(&domain).is_some() {
+        Some(Host::Domain(domain))
+    } else {
+        None
+    }
+}
+
+/// The host of an authority, which may be followed by the path, query or fragment.
+fn authority_host(authority: &str) -> Option<Host> {
+    let authority = authority
+        .split(&['/', '?', '#', '\\'][..])
+        .next()
+        .unwrap_or_default();
+    let host = match authority.rsplit_once('@') {
+        Some((_, host)) => host,
+        None => authority,
+    };
+
+    if let Some(literal) = host.strip_prefix('[') {
+        let (literal, _) = literal.split_once(']')?;
+        return literal
+            .parse::<Ipv6Addr>()
+            .ok()
+            .map(|ip| Host::Ip(ip.into()));
+    }
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host,
+    };
+    let host = host.trim_end_matches(&[']', '.'][..]);
+    if host.is_empty() {
+        return None;
+    }
+
+    if let Some(ip) = ipv4_literal(host) {
+        return Some(Host::Ip(ip.into()));
+    }
+    if !host.contains('.') {
+        return None;
+    }
+    normalize_domain(host).ok().map(Host::Domain)
+}
+
+/// Dotted, decimal and hexadecimal IPv4 addresses.
+fn ipv4_literal(host: &str) -> Option<Ipv4Addr> {
+    if let Ok(ip) = host.parse() {
+        return Some(ip);
+    }
+    let number = match host.strip_prefix("0x").or_else(|| host.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None if host.chars().all(|c| c.is_ascii_digit()) => host.parse().ok()?,
+        None => return None,
+    };
+    Some(Ipv4Addr::from(number))
+}
+
+fn deobfuscate(text: &str) -> String {
+    let mut text = text.to_owned();
+    for (pattern, replacement) in OBFUSCATIONS {
+        let lower = text.to_ascii_lowercase();
+        if !lower.contains(pattern) {
+            continue;
+        }
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, _) in lower.match_indices(pattern) {
+            replaced.push_str(&text[last..start]);
+            replaced.push_str(replacement);
+            last = start + pattern.len();
+        }
+        replaced.push_str(&text[last..]);
+        text = replaced;
+    }
+    text
+}
+
+/// Decodes numeric character references and the named ones that occur in URLs.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "period" => Some('.'),
+            "colon" => Some(':'),
+            "sol" => Some('/'),
+            "commat" => Some('@'),
+            _ => {
+                let code = match entity.strip_prefix('#')? {
+                    hex if hex.starts_with(&['x', 'X'][..]) => u32::from_str_radix(&hex[1..], 16),
+                    decimal => decimal.parse(),
+                };
+                code.ok().and_then(char::from_u32)
+            }
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn percent_decode(text: &str) -> String {
+    if !text.contains('%') {
+        return text.to_owned();
+    }
+
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
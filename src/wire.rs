@@ -0,0 +1,189 @@
+//! Minimal authoritative DNS server plumbing shared by the servers in this crate.
+
+use std::convert::TryFrom;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+use trust_dns_resolver::proto::{
+    op::{Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{
+        rdata::{SOA, TXT},
+        Name, RData, Record,
+    },
+};
+
+const UDP_PAYLOAD: usize = 512;
+/// Default bound for UDP queries and TCP connections handled at the same time.
+pub(crate) const MAX_PENDING: usize = 1024;
+/// Time a TCP connection may wait between queries before it is closed.
+const TCP_IDLE: Duration = Duration::from_secs(10);
+
+pub(crate) struct Reply {
+    pub(crate) code: ResponseCode,
+    pub(crate) answers: Vec<Record>,
+    pub(crate) authority: Vec<Record>,
+}
+
+impl Reply {
+    pub(crate) fn new(code: ResponseCode) -> Self {
+        Self {
+            code,
+            answers: Vec::new(),
+            authority: Vec::new(),
+        }
+    }
+}
+
+pub(crate) trait Handler: Send + Sync + 'static {
+    fn answer<'a>(&'a self, query: &'a Query) -> BoxFuture<'a, Reply>;
+}
+
+/// TXT record data, split into character strings of at most 255 bytes.
+pub(crate) fn txt(text: &str) -> RData {
+    RData::TXT(TXT::from_bytes(text.as_bytes().chunks(255).collect()))
+}
+
+/// SOA record for `origin`, `ttl` is also used as the negative caching TTL.
+pub(crate) fn soa(origin: &Name, ttl: u32) -> Record {
+    let soa = SOA::new(origin.clone(), origin.clone(), 1, 3600, 600, 86400, ttl);
+    Record::from_rdata(origin.clone(), ttl, RData::SOA(soa))
+}
+
+/// Answers each datagram while holding a permit of `pending`, datagrams arriving while all
+/// permits are taken are refused.
+pub(crate) async fn serve_udp(
+    socket: UdpSocket,
+    handler: Arc<dyn Handler>,
+    pending: Arc<Semaphore>,
+) -> io::Result<()> {
+    let socket = Arc::new(socket);
+    let mut buf = [0; 4096];
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let permit = match pending.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if let Some(response) = refuse(&buf[..len]) {
+                    let _ = socket.send_to(&response, peer).await;
+                }
+                continue;
+            }
+        };
+        let request = buf[..len].to_vec();
+        let socket = socket.clone();
+        let handler = handler.clone();
+
+        tokio::spawn(async move {
+            if let Some(response) = respond(&*handler, &request, UDP_PAYLOAD).await {
+                let _ = socket.send_to(&response, peer).await;
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Serves each connection while holding a permit of `pending`, further connections wait in the
+/// listen backlog until one closes.
+pub(crate) async fn serve_tcp(
+    listener: TcpListener,
+    handler: Arc<dyn Handler>,
+    pending: Arc<Semaphore>,
+) -> io::Result<()> {
+    loop {
+        let permit = pending
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+
+        tokio::spawn(async move {
+            let result = handle_tcp(stream, &*handler).await;
+            drop(permit);
+            result
+        });
+    }
+}
+
+async fn handle_tcp(mut stream: TcpStream, handler: &dyn Handler) -> io::Result<()> {
+    loop {
+        let mut len = [0; 2];
+        let read = tokio::time::timeout(TCP_IDLE, stream.read_exact(&mut len)).await;
+        if !matches!(read, Ok(Ok(_))) {
+            return Ok(());
+        }
+        let mut request = vec![0; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut request).await?;
+
+        if let Some(response) = respond(handler, &request, usize::from(u16::MAX)).await {
+            let len = u16::try_from(response.len()).expect("response fits max size");
+            stream.write_all(&len.to_be_bytes()).await?;
+            stream.write_all(&response).await?;
+        }
+    }
+}
+
+/// Parses `request` and starts its response, `None` for anything but queries.
+fn parse_query(request: &[u8]) -> Option<(Message, Message)> {
+    let request = Message::from_vec(request).ok()?;
+    if request.message_type() != MessageType::Query {
+        return None;
+    }
+
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_recursion_desired(request.recursion_desired())
+        .set_authoritative(true);
+    Some((request, response))
+}
+
+fn refuse(request: &[u8]) -> Option<Vec<u8>> {
+    let (request, mut response) = parse_query(request)?;
+    for query in request.queries() {
+        response.add_query(query.clone());
+    }
+    response.set_response_code(ResponseCode::Refused);
+    response.to_vec().ok()
+}
+
+async fn respond(handler: &dyn Handler, request: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let (request, mut response) = parse_query(request)?;
+
+    let query = match (request.op_code(), request.queries()) {
+        (OpCode::Query, [query]) => query,
+        (OpCode::Query, _) => {
+            response.set_response_code(ResponseCode::FormErr);
+            return response.to_vec().ok();
+        }
+        _ => {
+            response.set_response_code(ResponseCode::NotImp);
+            return response.to_vec().ok();
+        }
+    };
+    response.add_query(query.clone());
+
+    let reply = handler.answer(query).await;
+    response.set_response_code(reply.code);
+    response.add_answers(reply.answers);
+    response.add_name_servers(reply.authority);
+
+    let bytes = response.to_vec().ok()?;
+    if bytes.len() <= max_size {
+        return Some(bytes);
+    }
+
+    response.set_truncated(true);
+    response.take_answers();
+    response.take_name_servers();
+    response.to_vec().ok()
+}
@@ -0,0 +1,73 @@
+#![cfg(all(feature = "analysis", feature = "test-server"))]
+
+mod common;
+
+use common::{domain, list};
+use dnsbl::analysis::{split_mbox, AnalysisConfig};
+use dnsbl::received::HopStatus;
+use dnsbl::test_server::{TestServer, TestZone};
+
+const MESSAGE: &str = "Received: from mx.example.net (mx.example.net [10.0.0.1])\r
+\tby inbox.example.net with ESMTP\r
+Received: from mail.spam.example (mail.spam.example [1.2.3.4])\r
+\tby mx.example.net with ESMTP\r
+From: Spammer <info@mail.spam.example>\r
+Subject: Offer\r
+Message-ID: <1@spam.example>\r
+Content-Type: text/html\r
+\r
+<a href=\"hxxp://www[.]spam.example/buy\">buy</a> or see ham.example.com\r
+";
+
+#[tokio::test]
+async fn message() {
+    let zone = TestZone::new(&list("bl.test"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], Some("spam source"))
+        .list_domain(&domain("spam.example"), [127, 0, 1, 2], None);
+    let server = TestServer::start(vec![zone]).await.unwrap();
+    let dnsbl = server.builder().build().unwrap();
+    let config = AnalysisConfig {
+        ip_lists: vec![list("bl.test")],
+        domain_lists: vec![list("bl.test")],
+        ..AnalysisConfig::default()
+    };
+
+    let report = dnsbl
+        .analyze_message(&config, MESSAGE.as_bytes())
+        .await
+        .unwrap();
+    assert!(report.is_listed());
+    assert_eq!(report.subject.as_deref(), Some("Offer"));
+
+    assert_eq!(report.relays.len(), 2);
+    assert!(matches!(report.relays[0].status, HopStatus::Private));
+    match &report.relays[1].status {
+        HopStatus::Checked(results) => assert!(results.is_blocked()),
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    assert_eq!(report.helo.len(), 1);
+    assert!(report.helo[0].results.is_blocked());
+
+    assert_eq!(report.senders.len(), 1);
+    assert_eq!(report.senders[0].address, "info@mail.spam.example");
+    assert!(report.senders[0].domain.is_blocked());
+
+    assert_eq!(report.urls.len(), 2);
+    assert!(report.urls[0].results.is_blocked());
+    assert!(!report.urls[1].results.is_blocked());
+
+    assert!(report.to_markdown().contains("listed by **bl.test"));
+}
+
+#[test]
+fn mbox() {
+    let mbox = b"From a@example.com Mon Jan  1 00:00:00 2024\n\
+                 Subject: one\n\n>From the start\n\n\
+                 From b@example.com Mon Jan  1 00:00:00 2024\n\
+                 Subject: two\n\nbody\n";
+    let messages = split_mbox(mbox);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0], b"Subject: one\n\nFrom the start\n\n".to_vec());
+    assert_eq!(messages[1], b"Subject: two\n\nbody\n".to_vec());
+}
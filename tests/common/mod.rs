@@ -0,0 +1,51 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use dnsbl::{BlockList, DNSBLBuilder, Domain, DNSBL};
+
+pub fn list(name: &str) -> BlockList {
+    name.parse().unwrap()
+}
+
+pub fn domain(name: &str) -> Domain {
+    Domain::new(name).unwrap()
+}
+
+/// A resolver querying only `addr`, without retries, so failures show up at once.
+pub fn resolver(addr: SocketAddr) -> DNSBLBuilder {
+    DNSBL::builder()
+        .name_servers(&[addr.ip()], addr.port())
+        .attempts(1)
+}
+
+/// A name server that never answers, for as long as the socket lives.
+pub fn silent() -> UdpSocket {
+    UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap()
+}
+
+/// A zone failing every query with SERVFAIL.
+#[cfg(feature = "test-server")]
+pub fn broken(name: &str) -> dnsbl::test_server::TestZone {
+    use trust_dns_resolver::proto::op::ResponseCode;
+
+    dnsbl::test_server::TestZone::new(&list(name)).fail(ResponseCode::ServFail)
+}
+
+/// Serves `server` on a random local port.
+#[cfg(feature = "server")]
+pub async fn serve(server: &dnsbl::server::Server) -> SocketAddr {
+    use tokio::net::{TcpListener, UdpSocket};
+
+    for _ in 0..10 {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        if let Ok(tcp) = TcpListener::bind(addr).await {
+            let server = server.clone();
+            tokio::spawn(async move { server.serve(udp, tcp).await });
+            return addr;
+        }
+    }
+    panic!("no free port");
+}
@@ -0,0 +1,172 @@
+#![cfg(all(feature = "http", feature = "test-server"))]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use common::{list, resolver, silent};
+use dnsbl::http::{router, HttpConfig};
+use dnsbl::test_server::{TestServer, TestZone};
+use dnsbl::DNSBL;
+use tower::ServiceExt;
+
+async fn upstream() -> TestServer {
+    // without a TTL the resolver keeps no answers, see `healthz`
+    let a = TestZone::new(&list("a.test"))
+        .ttl(0)
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], None)
+        .list_ip([127, 0, 0, 2], [127, 0, 0, 2], None);
+    let b = TestZone::new(&list("b.test"))
+        .ttl(0)
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], None)
+        .list_ip([127, 0, 0, 2], [127, 0, 0, 2], None);
+    TestServer::start(vec![a, b]).await.unwrap()
+}
+
+fn app(upstream: &TestServer, config: HttpConfig) -> Router {
+    let dnsbl = resolver(upstream.addr())
+        .timeout(Duration::from_secs(1))
+        .build()
+        .unwrap();
+    router(Arc::new(dnsbl), config)
+}
+
+fn config() -> HttpConfig {
+    HttpConfig {
+        lists: vec![list("a.test"), list("b.test")],
+        ..HttpConfig::default()
+    }
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn post(app: &Router, uri: &str, json: &str) -> (StatusCode, String) {
+    let request = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json.to_owned()))
+        .unwrap();
+    send(app, request).await
+}
+
+#[tokio::test]
+async fn check_ip() {
+    let upstream = upstream().await;
+    let app = app(&upstream, config());
+
+    let (status, body) = get(&app, "/v1/check/ip/1.2.3.4").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#""listed":true"#), "{}", body);
+    assert!(body.contains(r#""list":"a.test""#), "{}", body);
+    assert!(body.contains(r#""list":"b.test""#), "{}", body);
+
+    let (status, body) = get(&app, "/v1/check/ip/1.2.3.5?lists=b.test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#""listed":false"#), "{}", body);
+    assert!(!body.contains("a.test"), "{}", body);
+    assert!(body.contains(r#""list":"b.test""#), "{}", body);
+}
+
+#[tokio::test]
+async fn unknown_list() {
+    let upstream = upstream().await;
+    let app = app(&upstream, config());
+
+    let (status, body) = get(&app, "/v1/check/ip/1.2.3.4?lists=a.test,c.test").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("unknown list c.test"), "{}", body);
+
+    let (status, _) = post(
+        &app,
+        "/v1/check",
+        r#"{"ips": ["1.2.3.4"], "lists": ["c.test"]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn max_targets() {
+    let upstream = upstream().await;
+    let config = HttpConfig {
+        max_targets: 2,
+        ..config()
+    };
+    let app = app(&upstream, config);
+
+    let (status, body) = post(
+        &app,
+        "/v1/check",
+        r#"{"ips": ["1.2.3.4"], "domains": ["example.com"]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#""target":"1.2.3.4""#), "{}", body);
+    assert!(body.contains(r#""target":"example.com""#), "{}", body);
+
+    let (status, body) = post(
+        &app,
+        "/v1/check",
+        r#"{"ips": ["1.2.3.4", "1.2.3.5"], "domains": ["example.com"]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("at most 2 targets"), "{}", body);
+}
+
+#[tokio::test]
+async fn timeout() {
+    // a name server that never answers
+    let silent = silent();
+    let addr = silent.local_addr().unwrap();
+    let dnsbl = DNSBL::builder()
+        .name_servers(&[addr.ip()], addr.port())
+        .build()
+        .unwrap();
+    let config = HttpConfig {
+        timeout: Duration::from_millis(100),
+        ..config()
+    };
+    let app = router(Arc::new(dnsbl), config);
+
+    let (status, body) = get(&app, "/v1/check/ip/1.2.3.4").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(body.contains("request timed out"), "{}", body);
+}
+
+#[tokio::test]
+async fn healthz() {
+    let upstream = upstream().await;
+    let cached = app(&upstream, config());
+    let uncached = app(
+        &upstream,
+        HttpConfig {
+            health_interval: Duration::from_secs(0),
+            ..config()
+        },
+    );
+
+    let (status, body) = get(&cached, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(r#""healthy":true"#), "{}", body);
+    assert_eq!(get(&uncached, "/healthz").await.0, StatusCode::OK);
+
+    // the cached reports outlive the lists
+    drop(upstream);
+    assert_eq!(get(&cached, "/healthz").await.0, StatusCode::OK);
+    let (status, body) = get(&uncached, "/healthz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body.contains(r#""healthy":false"#), "{}", body);
+}
@@ -0,0 +1,147 @@
+#![cfg(all(feature = "http", feature = "test-server"))]
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use common::{list, resolver};
+use dnsbl::layer::{BlockConfig, BlockLayer};
+use dnsbl::proxy_protocol::ProxyHeader;
+use dnsbl::test_server::{TestServer, TestZone};
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+async fn upstream() -> TestServer {
+    // without a TTL the resolver keeps no answers, so only the layer remembers decisions
+    let zone = TestZone::new(&list("bl.test")).ttl(0).list_ip(
+        [1, 2, 3, 4],
+        [127, 0, 0, 2],
+        Some("spam source"),
+    );
+    TestServer::start(vec![zone]).await.unwrap()
+}
+
+fn layer(upstream: &TestServer, config: BlockConfig) -> BlockLayer {
+    let dnsbl = resolver(upstream.addr())
+        .timeout(Duration::from_secs(1))
+        .build()
+        .unwrap();
+    BlockLayer::new(Arc::new(dnsbl), config)
+}
+
+/// A request from `peer` with the given `X-Forwarded-For` headers.
+fn request(peer: &str, forwarded: &[&str]) -> Request<()> {
+    let mut request = Request::builder();
+    for value in forwarded {
+        request = request.header("x-forwarded-for", *value);
+    }
+    let mut request = request.body(()).unwrap();
+    let peer = SocketAddr::new(ip(peer), 4321);
+    request.extensions_mut().insert(ConnectInfo(peer));
+    request
+}
+
+#[tokio::test]
+async fn forwarded_for() {
+    let upstream = upstream().await;
+    let layer = layer(
+        &upstream,
+        BlockConfig {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..BlockConfig::default()
+        },
+    );
+    let client = |peer: &str, forwarded: &[&str]| layer.client_address(&request(peer, forwarded));
+
+    // only trusted peers may forward
+    assert_eq!(client("203.0.113.5", &["1.2.3.4"]), Some(ip("203.0.113.5")));
+    assert_eq!(client("10.0.0.1", &[]), Some(ip("10.0.0.1")));
+    assert_eq!(client("10.0.0.1", &["1.2.3.4"]), Some(ip("1.2.3.4")));
+
+    // the walk stops at the first untrusted hop from the right
+    assert_eq!(
+        client("10.0.0.1", &["6.6.6.6, 1.2.3.4, 10.0.0.7"]),
+        Some(ip("1.2.3.4"))
+    );
+    assert_eq!(
+        client("10.0.0.1", &["6.6.6.6", "1.2.3.4, 10.0.0.2"]),
+        Some(ip("1.2.3.4"))
+    );
+    assert_eq!(client("10.0.0.1", &["10.0.0.3"]), Some(ip("10.0.0.3")));
+    assert_eq!(
+        client("10.0.0.1", &["1.2.3.4, garbage, 10.0.0.3"]),
+        Some(ip("10.0.0.3"))
+    );
+    assert_eq!(
+        client("10.0.0.1", &["2001:db8::1 , 10.0.0.3"]),
+        Some(ip("2001:db8::1"))
+    );
+}
+
+#[tokio::test]
+async fn proxy_header() {
+    let upstream = upstream().await;
+    let layer = layer(&upstream, BlockConfig::default());
+
+    let mut request = request("10.0.0.1", &[]);
+    request.extensions_mut().insert(ProxyHeader {
+        source: Some("1.2.3.4:4321".parse().unwrap()),
+        destination: Some("10.0.0.1:80".parse().unwrap()),
+    });
+    assert_eq!(layer.client_address(&request), Some(ip("1.2.3.4")));
+
+    request.extensions_mut().insert(ProxyHeader {
+        source: None,
+        destination: None,
+    });
+    assert_eq!(layer.client_address(&request), None);
+
+    assert_eq!(layer.client_address(&Request::new(())), None);
+}
+
+#[tokio::test]
+async fn decisions() {
+    let upstream = upstream().await;
+    let config = BlockConfig {
+        lists: vec![list("bl.test")],
+        reject_on_error: true,
+        ..BlockConfig::default()
+    };
+    let cached = layer(&upstream, config.clone());
+    let uncached = layer(
+        &upstream,
+        BlockConfig {
+            decision_ttl: Duration::from_secs(0),
+            ..config
+        },
+    );
+    let listed = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+    let clean = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 5));
+
+    let response = cached.check(listed).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(cached.check(clean).await.is_none());
+    assert!(uncached.check(clean).await.is_none());
+
+    // the lists are gone, only remembered decisions still stand
+    drop(upstream);
+    let response = cached.check(listed).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(cached.check(clean).await.is_none());
+
+    let unknown = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 6));
+    let response = cached.check(unknown).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let response = uncached.check(clean).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // failed checks are not remembered either
+    let response = cached.check(unknown).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
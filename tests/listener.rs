@@ -0,0 +1,159 @@
+#![cfg(all(feature = "listener", feature = "test-server"))]
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{broken, list, resolver, silent};
+use dnsbl::listener::{FilteredListener, ListenerConfig, Verdict};
+use dnsbl::test_server::{TestServer, TestZone};
+use dnsbl::{AddressPolicy, DNSBL};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+const BANNER: &str = "554 Your address is listed\r\n";
+
+/// Test connections come from `127.0.0.1`, which `bl.test` lists.
+async fn upstream() -> TestServer {
+    let listed = TestZone::new(&list("bl.test")).list_ip([127, 0, 0, 1], [127, 0, 0, 2], None);
+    let clean = TestZone::new(&list("clean.test"));
+    let broken = broken("broken.test");
+    TestServer::start(vec![listed, clean, broken])
+        .await
+        .unwrap()
+}
+
+fn dnsbl(addr: SocketAddr) -> Arc<DNSBL> {
+    let dnsbl = resolver(addr)
+        .address_policy(AddressPolicy::query_all())
+        .build()
+        .unwrap();
+    Arc::new(dnsbl)
+}
+
+fn config(name: &str) -> ListenerConfig {
+    ListenerConfig {
+        lists: vec![list(name)],
+        banner: Some(BANNER.to_owned()),
+        ..ListenerConfig::default()
+    }
+}
+
+async fn listen(dnsbl: Arc<DNSBL>, config: ListenerConfig) -> FilteredListener {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    FilteredListener::new(listener, dnsbl, config).unwrap()
+}
+
+/// Connects and returns what the listener wrote before closing the connection.
+async fn rejected(listener: &FilteredListener) -> String {
+    let mut stream = TcpStream::connect(listener.local_addr()).await.unwrap();
+    let mut banner = String::new();
+    stream.read_to_string(&mut banner).await.unwrap();
+    banner
+}
+
+/// Connects and returns the accepted connection's peer address as seen by the listener.
+async fn accepted(listener: &mut FilteredListener) -> SocketAddr {
+    let stream = TcpStream::connect(listener.local_addr()).await.unwrap();
+    let (_, peer) = listener.accept().await.unwrap();
+    assert_eq!(peer, stream.local_addr().unwrap());
+    peer
+}
+
+async fn nothing_accepted(listener: &mut FilteredListener) {
+    let accept = tokio::time::timeout(Duration::from_millis(200), listener.accept());
+    assert!(accept.await.is_err(), "a rejected peer was accepted");
+}
+
+#[tokio::test]
+async fn listed() {
+    let upstream = upstream().await;
+    let mut listener = listen(dnsbl(upstream.addr()), config("bl.test")).await;
+
+    assert_eq!(rejected(&listener).await, BANNER);
+    nothing_accepted(&mut listener).await;
+
+    let silent = ListenerConfig {
+        banner: None,
+        ..config("bl.test")
+    };
+    let listener = listen(dnsbl(upstream.addr()), silent).await;
+    assert_eq!(rejected(&listener).await, "");
+
+    let accepting = ListenerConfig {
+        on_listed: Verdict::Accept,
+        ..config("bl.test")
+    };
+    let mut listener = listen(dnsbl(upstream.addr()), accepting).await;
+    accepted(&mut listener).await;
+}
+
+#[tokio::test]
+async fn clean() {
+    let upstream = upstream().await;
+    let mut listener = listen(dnsbl(upstream.addr()), config("clean.test")).await;
+
+    let peer = accepted(&mut listener).await;
+    assert_eq!(peer.ip(), Ipv4Addr::LOCALHOST);
+}
+
+#[tokio::test]
+async fn on_error() {
+    let upstream = upstream().await;
+
+    let mut listener = listen(dnsbl(upstream.addr()), config("broken.test")).await;
+    accepted(&mut listener).await;
+
+    let config = ListenerConfig {
+        on_error: Verdict::Reject,
+        ..config("broken.test")
+    };
+    let mut listener = listen(dnsbl(upstream.addr()), config).await;
+    assert_eq!(rejected(&listener).await, BANNER);
+    nothing_accepted(&mut listener).await;
+}
+
+#[tokio::test]
+async fn on_timeout() {
+    // a name server that never answers
+    let silent = silent();
+    let dnsbl = dnsbl(silent.local_addr().unwrap());
+
+    let config = ListenerConfig {
+        timeout: Duration::from_millis(100),
+        ..config("bl.test")
+    };
+    let mut listener = listen(dnsbl.clone(), config.clone()).await;
+    accepted(&mut listener).await;
+
+    let config = ListenerConfig {
+        on_timeout: Verdict::Reject,
+        ..config
+    };
+    let mut listener = listen(dnsbl, config).await;
+    assert_eq!(rejected(&listener).await, BANNER);
+    nothing_accepted(&mut listener).await;
+}
+
+#[tokio::test]
+async fn max_pending() {
+    let silent = silent();
+    let timeout = Duration::from_millis(300);
+    let config = ListenerConfig {
+        timeout,
+        max_pending: 1,
+        ..config("bl.test")
+    };
+    let mut listener = listen(dnsbl(silent.local_addr().unwrap()), config).await;
+
+    // the second check only starts once the first one timed out
+    let start = Instant::now();
+    let _first = TcpStream::connect(listener.local_addr()).await.unwrap();
+    let _second = TcpStream::connect(listener.local_addr()).await.unwrap();
+    listener.accept().await.unwrap();
+    assert!(start.elapsed() >= timeout);
+    listener.accept().await.unwrap();
+    assert!(start.elapsed() >= timeout * 2);
+}
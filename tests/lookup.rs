@@ -0,0 +1,104 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use dnsbl::{BlockList, BlockStatus, CacheConfig, ErrorKind, MemoryLookup, Name, DNSBL};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+
+fn list() -> BlockList {
+    "bl.test".parse().unwrap()
+}
+
+fn name(name: &str) -> Name {
+    Name::from_ascii(name).unwrap()
+}
+
+#[tokio::test]
+async fn answers() {
+    let lookup = MemoryLookup::new().list_ip(
+        &list(),
+        Ipv4Addr::new(1, 2, 3, 4),
+        Ipv4Addr::new(127, 0, 0, 2),
+        Some("spam source"),
+    );
+    let dnsbl = DNSBL::with_lookup(lookup);
+
+    let status = dnsbl.check_ip(&list(), Ipv4Addr::new(1, 2, 3, 4)).await;
+    assert_eq!(
+        status,
+        BlockStatus::Blocked {
+            addresses: vec![Ipv4Addr::new(127, 0, 0, 2)],
+            reasons: Default::default(),
+            message: Some("spam source".to_owned()),
+        }
+    );
+    let status = dnsbl.check_ip(&list(), Ipv4Addr::new(1, 2, 3, 5)).await;
+    assert_eq!(status, BlockStatus::NotBlocked);
+}
+
+#[tokio::test]
+async fn errors() {
+    let lookup = MemoryLookup::new()
+        .error(
+            name("4.3.2.1.bl.test."),
+            ResolveError::from(ResolveErrorKind::Timeout),
+        )
+        .error(
+            name("5.3.2.1.bl.test."),
+            ResolveError::from("broken".to_owned()),
+        );
+    let dnsbl = DNSBL::with_lookup(lookup);
+
+    match dnsbl.check_ip(&list(), Ipv4Addr::new(1, 2, 3, 4)).await {
+        BlockStatus::Error { kind, .. } => assert_eq!(kind, ErrorKind::Timeout),
+        status => panic!("unexpected status {:?}", status),
+    }
+    match dnsbl.check_ip(&list(), Ipv4Addr::new(1, 2, 3, 5)).await {
+        BlockStatus::Error { kind, source } => {
+            assert_eq!(kind, ErrorKind::Other);
+            assert_eq!(source.to_string(), "broken");
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn ttl() {
+    tokio::time::pause();
+    let config = CacheConfig {
+        min_ttl: Duration::from_secs(0),
+        ..CacheConfig::default()
+    };
+    let ip = Ipv4Addr::new(1, 2, 3, 4);
+    let code = Ipv4Addr::new(127, 0, 0, 2);
+
+    // Answers with a TTL of 0 are not cached.
+    let lookup = MemoryLookup::new()
+        .ttl(Duration::from_secs(0))
+        .list_ip(&list(), ip, code, None);
+    let dnsbl = DNSBL::builder()
+        .lookup(lookup)
+        .cache(config.clone())
+        .build()
+        .unwrap();
+    dnsbl.check_ip(&list(), ip).await;
+    dnsbl.check_ip(&list(), Ipv4Addr::new(1, 2, 3, 5)).await;
+    assert_eq!(dnsbl.cache_stats().unwrap().entries, 0);
+
+    // Others are cached until their TTL runs out.
+    let lookup = MemoryLookup::new()
+        .ttl(Duration::from_secs(1))
+        .list_ip(&list(), ip, code, None);
+    let dnsbl = DNSBL::builder()
+        .lookup(lookup)
+        .cache(config)
+        .build()
+        .unwrap();
+    dnsbl.check_ip(&list(), ip).await;
+    dnsbl.check_ip(&list(), ip).await;
+    let stats = dnsbl.cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+    tokio::time::advance(Duration::from_millis(1100)).await;
+    dnsbl.check_ip(&list(), ip).await;
+    assert_eq!(dnsbl.cache_stats().unwrap().misses, 2);
+}
@@ -0,0 +1,82 @@
+use std::net::IpAddr;
+
+use dnsbl::{AddressClass, AddressPolicy, Network};
+
+fn class(ip: &str) -> Option<AddressClass> {
+    AddressClass::of(ip.parse().unwrap())
+}
+
+#[test]
+fn address_classes() {
+    for (ip, expected) in [
+        ("10.1.2.3", AddressClass::Private),
+        ("100.64.0.1", AddressClass::SharedAddressSpace),
+        ("127.0.0.2", AddressClass::Loopback),
+        ("::1", AddressClass::Loopback),
+        ("fe80::1", AddressClass::LinkLocal),
+        ("203.0.113.7", AddressClass::Documentation),
+        ("2001:db8::1", AddressClass::Documentation),
+        ("fd00::1", AddressClass::UniqueLocal),
+        ("0.0.0.0", AddressClass::Unspecified),
+        ("255.255.255.255", AddressClass::Multicast),
+        ("ff02::1", AddressClass::Multicast),
+        ("0.0.0.123", AddressClass::Reserved),
+        ("240.0.0.1", AddressClass::Reserved),
+        ("198.19.255.255", AddressClass::Reserved),
+        ("192.0.0.8", AddressClass::Reserved),
+        ("100::1", AddressClass::Reserved),
+        ("2001:2:0:ffff::1", AddressClass::Reserved),
+        ("::ffff:192.168.1.1", AddressClass::Private),
+    ]
+    .iter()
+    {
+        assert_eq!(class(ip), Some(*expected), "{}", ip);
+    }
+
+    for ip in [
+        "1.2.3.4",
+        "198.17.255.255",
+        "198.20.0.0",
+        "192.0.1.1",
+        "2a00:1450::1",
+        "100:0:0:1::1",
+        "2001:2:1::1",
+    ]
+    .iter()
+    {
+        assert_eq!(class(ip), None, "{}", ip);
+    }
+}
+
+#[test]
+fn policy() {
+    let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+    let policy = AddressPolicy::default();
+
+    assert!(policy.applies(ip("1.2.3.4")));
+    assert!(policy.applies(ip("127.0.0.1")));
+    assert!(policy.applies(ip("127.0.0.2")));
+    assert!(policy.applies(ip("::ffff:127.0.0.2")));
+    assert!(!policy.applies(ip("127.0.0.3")));
+    assert!(!policy.clone().test_points(false).applies(ip("127.0.0.2")));
+    assert!(!policy.applies(ip("0.0.0.123")));
+    assert!(policy
+        .clone()
+        .allow(AddressClass::Reserved)
+        .applies(ip("0.0.0.123")));
+    assert!(AddressPolicy::query_all().applies(ip("127.0.0.2")));
+
+    assert_eq!(policy.normalize(ip("::ffff:1.2.3.4")), ip("1.2.3.4"));
+    assert_eq!(
+        policy.map_ipv4(false).normalize(ip("::ffff:1.2.3.4")),
+        ip("::ffff:1.2.3.4")
+    );
+}
+
+#[test]
+fn network() {
+    let network: Network = "198.18.0.0/15".parse().unwrap();
+    assert!(network.contains("198.19.1.1".parse().unwrap()));
+    assert!(!network.contains("198.20.0.0".parse().unwrap()));
+    assert_eq!(network.to_string(), "198.18.0.0/15");
+}
@@ -0,0 +1,154 @@
+#![cfg(all(feature = "policy", feature = "test-server"))]
+
+mod common;
+
+use std::io;
+
+use common::{broken, domain, list, resolver};
+use dnsbl::policy::{Action, PolicyConfig, PolicyRequest, PolicyServer, Reply};
+use dnsbl::test_server::{TestServer, TestZone};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+async fn policy_server() -> (TestServer, PolicyServer) {
+    let zone = TestZone::new(&list("bl.test"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], Some("spam source"))
+        .list_domain(&domain("spam.co.uk"), [127, 0, 1, 2], None);
+    let failing = broken("broken.test");
+    let server = TestServer::start(vec![zone, failing]).await.unwrap();
+    let dnsbl = resolver(server.addr()).build().unwrap();
+    let config = PolicyConfig {
+        client_lists: vec![list("bl.test")],
+        helo_lists: vec![list("bl.test")],
+        on_error: Action::Defer,
+        ..PolicyConfig::default()
+    };
+    (server, PolicyServer::new(dnsbl, config))
+}
+
+#[test]
+fn reply_format() {
+    let reply = Reply {
+        action: Action::Reject,
+        text: Some("listed\r\nby spam.test".to_owned()),
+    };
+    assert_eq!(reply.to_string(), "action=REJECT listedby spam.test");
+
+    let reply = Reply {
+        action: Action::Defer,
+        text: None,
+    };
+    assert_eq!(reply.to_string(), "action=DEFER");
+
+    let reply = Reply {
+        action: Action::Dunno,
+        text: Some("ignored".to_owned()),
+    };
+    assert_eq!(reply.to_string(), "action=DUNNO");
+}
+
+#[test]
+fn request_attributes() {
+    let request: PolicyRequest = [
+        ("client_address", "192.0.2.1"),
+        ("helo_name", "Mail.Example.COM"),
+        ("sender", "Info@Example.net"),
+        ("recipient", ""),
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    assert_eq!(request.client_address(), Some("192.0.2.1".parse().unwrap()));
+    assert_eq!(request.helo_domain(), Some(domain("mail.example.com")));
+    assert_eq!(request.sender_domain(), Some(domain("example.net")));
+    assert_eq!(request.get("recipient"), None);
+}
+
+#[tokio::test]
+async fn decide() {
+    let (_server, policy) = policy_server().await;
+
+    let request: PolicyRequest = [("client_address", "1.2.3.4")].iter().copied().collect();
+    let reply = policy.decide(&request).await;
+    assert_eq!(reply.action, Action::Reject);
+    assert_eq!(reply.text.as_deref(), Some("spam source"));
+
+    let request: PolicyRequest = [
+        ("client_address", "1.2.3.5"),
+        ("helo_name", "mail.spam.co.uk"),
+    ]
+    .iter()
+    .copied()
+    .collect();
+    let reply = policy.decide(&request).await;
+    assert_eq!(reply.action, Action::Reject);
+    assert!(reply.text.unwrap().contains("is listed by bl.test"));
+
+    let request: PolicyRequest = [("client_address", "1.2.3.5")].iter().copied().collect();
+    let reply = policy.decide(&request).await;
+    assert_eq!(
+        reply,
+        Reply {
+            action: Action::Dunno,
+            text: None
+        }
+    );
+}
+
+#[tokio::test]
+async fn decide_on_error() {
+    let (server, _) = policy_server().await;
+    let dnsbl = resolver(server.addr()).build().unwrap();
+    let config = PolicyConfig {
+        client_lists: vec![list("broken.test")],
+        on_error: Action::Defer,
+        ..PolicyConfig::default()
+    };
+    let policy = PolicyServer::new(dnsbl, config);
+
+    let request: PolicyRequest = [("client_address", "1.2.3.4")].iter().copied().collect();
+    let reply = policy.decide(&request).await;
+    assert_eq!(reply.action, Action::Defer);
+    assert!(reply
+        .text
+        .unwrap()
+        .starts_with("Temporary failure querying broken.test"));
+}
+
+#[tokio::test]
+async fn handle() {
+    let (_server, policy) = policy_server().await;
+    let (mut client, connection) = tokio::io::duplex(64 * 1024);
+    let handler = tokio::spawn(async move { policy.handle(connection).await });
+
+    client
+        .write_all(b"request=smtpd_access_policy\nclient_address=1.2.3.4\n\n")
+        .await
+        .unwrap();
+    let mut reply = vec![0; "action=REJECT spam source\n\n".len()];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, b"action=REJECT spam source\n\n");
+
+    client.write_all(&[b'a'; 8192]).await.unwrap();
+    let error = handler.await.unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn too_many_attributes() {
+    let (_server, policy) = policy_server().await;
+    let (mut client, connection) = tokio::io::duplex(64 * 1024);
+    let handler = tokio::spawn(async move { policy.handle(connection).await });
+
+    for i in 0..1000 {
+        if client
+            .write_all(format!("attribute{}=value\n", i).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    let error = handler.await.unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
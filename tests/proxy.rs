@@ -0,0 +1,172 @@
+#![cfg(all(feature = "server", feature = "test-server"))]
+
+mod common;
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use common::{broken, domain, list, resolver, serve};
+use dnsbl::proxy::ProxyZone;
+use dnsbl::server::Server;
+use dnsbl::test_server::{TestServer, TestZone};
+use dnsbl::{AddressPolicy, BlockStatus, ErrorKind, Health};
+
+async fn upstream() -> TestServer {
+    let a = TestZone::new(&list("a.test"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], Some("spam"))
+        .list_ip([127, 0, 0, 2], [127, 0, 0, 2], None);
+    let b = TestZone::new(&list("b.test"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], None)
+        .list_domain(&domain("spam.example"), [127, 0, 1, 2], None);
+    let broken = broken("broken.test");
+    TestServer::start(vec![a, b, broken]).await.unwrap()
+}
+
+fn proxy(upstream: &TestServer, lists: &[&str]) -> ProxyZone {
+    let dnsbl = resolver(upstream.addr()).build().unwrap();
+    ProxyZone::new(
+        Arc::new(dnsbl),
+        lists.iter().map(|name| list(name)).collect(),
+    )
+}
+
+/// Returned addresses, reasons and message, panics unless blocked.
+fn blocked(status: BlockStatus) -> (Vec<Ipv4Addr>, Vec<String>, Option<String>) {
+    match status {
+        BlockStatus::Blocked {
+            addresses,
+            reasons,
+            message,
+        } => (addresses, reasons.into_iter().collect(), message),
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn aggregate() {
+    let upstream = upstream().await;
+    let dnsbl = resolver(upstream.addr()).build().unwrap();
+    let zone = proxy(&upstream, &["broken.test", "a.test", "b.test"]);
+
+    let results = dnsbl
+        .check_ip_all(zone.lists(), Ipv4Addr::new(1, 2, 3, 4))
+        .await;
+    let (addresses, reasons, message) = blocked(zone.aggregate(&results));
+    assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 12)]);
+    assert_eq!(reasons, ["a.test", "b.test"]);
+    assert_eq!(message.as_deref(), Some("a.test: spam; b.test"));
+
+    // without any listing the failed list decides
+    let results = dnsbl
+        .check_ip_all(zone.lists(), Ipv4Addr::new(1, 2, 3, 5))
+        .await;
+    match zone.aggregate(&results) {
+        BlockStatus::Error { kind, .. } => assert_eq!(kind, ErrorKind::ServerFailure),
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    let zone = proxy(&upstream, &["a.test", "b.test"]);
+    let results = dnsbl
+        .check_ip_all(zone.lists(), Ipv4Addr::new(1, 2, 3, 5))
+        .await;
+    assert!(matches!(zone.aggregate(&results), BlockStatus::NotBlocked));
+}
+
+#[tokio::test]
+async fn last_list() {
+    let upstream = upstream().await;
+    let mut names: Vec<String> = (1..ProxyZone::MAX_LISTS)
+        .map(|i| format!("unknown{}.test", i))
+        .collect();
+    names.push("a.test".to_owned());
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let zone = proxy(&upstream, &names);
+
+    let (addresses, reasons, _) = blocked(zone.check_query("4.3.2.1").await);
+    assert_eq!(addresses, [Ipv4Addr::new(127, 128, 0, 0)]);
+    assert_eq!(reasons, ["a.test"]);
+}
+
+#[tokio::test]
+#[should_panic(expected = "at most 23 lists")]
+async fn too_many_lists() {
+    let upstream = upstream().await;
+    proxy(&upstream, &["a.test"; ProxyZone::MAX_LISTS + 1]);
+}
+
+#[tokio::test]
+async fn check_query() {
+    let upstream = upstream().await;
+    let zone = proxy(&upstream, &["a.test", "b.test"]);
+
+    let (addresses, _, _) = blocked(zone.check_query("4.3.2.1").await);
+    assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 6)]);
+    let (addresses, reasons, _) = blocked(zone.check_query("spam.example").await);
+    assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 4)]);
+    assert_eq!(reasons, ["b.test"]);
+    assert!(matches!(
+        zone.check_query("5.3.2.1").await,
+        BlockStatus::NotBlocked
+    ));
+}
+
+#[tokio::test]
+async fn served() {
+    let upstream = upstream().await;
+    let server = Server::new();
+    server.set_proxy_zone(
+        &"proxy.test".parse().unwrap(),
+        proxy(&upstream, &["a.test", "b.test"]),
+        60,
+    );
+    server.set_proxy_zone(
+        &"broken.proxy.test".parse().unwrap(),
+        proxy(&upstream, &["broken.test"]),
+        60,
+    );
+    let addr = serve(&server).await;
+    let dnsbl = resolver(addr).build().unwrap();
+
+    let (addresses, _, message) = blocked(
+        dnsbl
+            .check_ip(&list("proxy.test"), Ipv4Addr::new(1, 2, 3, 4))
+            .await,
+    );
+    assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 6)]);
+    assert_eq!(message.as_deref(), Some("a.test: spam; b.test"));
+
+    assert!(matches!(
+        dnsbl
+            .check_ip(&list("proxy.test"), Ipv4Addr::new(1, 2, 3, 5))
+            .await,
+        BlockStatus::NotBlocked
+    ));
+    match dnsbl
+        .check_ip(&list("broken.proxy.test"), Ipv4Addr::new(1, 2, 3, 4))
+        .await
+    {
+        BlockStatus::Error { kind, .. } => assert_eq!(kind, ErrorKind::ServerFailure),
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn health_check() {
+    let upstream = upstream().await;
+    // the test points reach the lists even through a policy skipping them
+    let dnsbl = resolver(upstream.addr())
+        .address_policy(AddressPolicy::default().test_points(false))
+        .build()
+        .unwrap();
+    let server = Server::new();
+    server.set_proxy_zone(
+        &"proxy.test".parse().unwrap(),
+        ProxyZone::new(Arc::new(dnsbl), vec![list("a.test"), list("b.test")]),
+        60,
+    );
+    let addr = serve(&server).await;
+    let dnsbl = resolver(addr).build().unwrap();
+
+    let report = dnsbl.health_check(&list("proxy.test")).await;
+    assert_eq!(report.ipv4, Health::Alive);
+}
@@ -0,0 +1,202 @@
+#![cfg(feature = "http")]
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::routing::get;
+use axum::Router;
+use dnsbl::proxy_protocol::{read_header, ProxyAcceptor, ProxyHeader};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn addr(addr: &str) -> Option<SocketAddr> {
+    Some(addr.parse().unwrap())
+}
+
+fn local() -> ProxyHeader {
+    ProxyHeader {
+        source: None,
+        destination: None,
+    }
+}
+
+/// Parses the header at the start of `data` and returns the remaining bytes.
+async fn parse(data: &[u8]) -> io::Result<(ProxyHeader, &[u8])> {
+    let mut reader = data;
+    let header = read_header(&mut reader).await?;
+    Ok((header, reader))
+}
+
+fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.push(command);
+    header.push(family);
+    header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    header.extend_from_slice(payload);
+    header
+}
+
+#[tokio::test]
+async fn v1() {
+    let (header, rest) = parse(b"PROXY TCP4 192.0.2.1 192.0.2.2 4321 80\r\nGET /")
+        .await
+        .unwrap();
+    assert_eq!(header.source, addr("192.0.2.1:4321"));
+    assert_eq!(header.destination, addr("192.0.2.2:80"));
+    assert_eq!(rest, b"GET /");
+
+    let (header, _) = parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 4321 443\r\n")
+        .await
+        .unwrap();
+    assert_eq!(header.source, addr("[2001:db8::1]:4321"));
+    assert_eq!(header.destination, addr("[2001:db8::2]:443"));
+
+    let (header, rest) = parse(b"PROXY UNKNOWN\r\nrest").await.unwrap();
+    assert_eq!(header, local());
+    assert_eq!(rest, b"rest");
+}
+
+#[tokio::test]
+async fn v1_invalid() {
+    let mut long = b"PROXY TCP4 ".to_vec();
+    long.extend_from_slice(&[b'1'; 200]);
+    long.extend_from_slice(b"\r\n");
+
+    for data in [
+        &b"PROXY TCP4 192.0.2.1 192.0.2.2 4321\r\n"[..],
+        b"PROXY TCP4 192.0.2.1 192.0.2.2 4321 65536\r\n",
+        b"PROXY TCP4 192.0.2.x 192.0.2.2 4321 80\r\n",
+        b"PROXY UDP4 192.0.2.1 192.0.2.2 4321 80\r\n",
+        b"GET / HTTP/1.1\r\n\r\n",
+        long.as_slice(),
+    ]
+    .iter()
+    {
+        let error = parse(data).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", data);
+    }
+
+    let error = parse(b"PROXY TCP4 192.0.2.1").await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[tokio::test]
+async fn v2_addresses() {
+    let mut data = v2(0x21, 0x11, &[192, 0, 2, 1, 192, 0, 2, 2, 0x10, 0xe1, 0, 80]);
+    data.extend_from_slice(b"GET /");
+    let (header, rest) = parse(&data).await.unwrap();
+    assert_eq!(header.source, addr("192.0.2.1:4321"));
+    assert_eq!(header.destination, addr("192.0.2.2:80"));
+    assert_eq!(rest, b"GET /");
+
+    let mut payload = vec![0x20, 0x01, 0x0d, 0xb8];
+    payload.extend_from_slice(&[0; 11]);
+    payload.push(1);
+    payload.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+    payload.extend_from_slice(&[0; 11]);
+    payload.push(2);
+    payload.extend_from_slice(&[0x10, 0xe1, 0x01, 0xbb]);
+    let (header, _) = parse(&v2(0x21, 0x21, &payload)).await.unwrap();
+    assert_eq!(header.source, addr("[2001:db8::1]:4321"));
+    assert_eq!(header.destination, addr("[2001:db8::2]:443"));
+
+    // TLVs after the addresses are skipped
+    let mut data = v2(
+        0x21,
+        0x11,
+        &[192, 0, 2, 1, 192, 0, 2, 2, 0x10, 0xe1, 0, 80, 0x04, 0, 1, 0],
+    );
+    data.extend_from_slice(b"GET /");
+    let (header, rest) = parse(&data).await.unwrap();
+    assert_eq!(header.source, addr("192.0.2.1:4321"));
+    assert_eq!(rest, b"GET /");
+}
+
+#[tokio::test]
+async fn v2_local() {
+    let data = v2(0x20, 0x00, &[]);
+    let (header, rest) = parse(&data).await.unwrap();
+    assert_eq!(header, local());
+    assert!(rest.is_empty());
+
+    // unspecified and unix families carry no usable address
+    let (header, _) = parse(&v2(0x21, 0x00, &[])).await.unwrap();
+    assert_eq!(header, local());
+    let (header, _) = parse(&v2(0x21, 0x31, &[0; 216])).await.unwrap();
+    assert_eq!(header, local());
+}
+
+#[tokio::test]
+async fn v2_invalid() {
+    for data in [
+        v2(0x11, 0x11, &[0; 12]),
+        v2(0x22, 0x11, &[0; 12]),
+        v2(0x21, 0x11, &[0; 4]),
+        v2(0x21, 0x21, &[0; 12]),
+    ]
+    .iter()
+    {
+        let error = parse(data).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", data);
+    }
+
+    let mut data = v2(0x21, 0x11, &[0; 12]);
+    data.truncate(data.len() - 1);
+    let error = parse(&data).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+/// Serves the client address axum sees, through a [`ProxyAcceptor`].
+async fn serve() -> SocketAddr {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route(
+        "/",
+        get(|ConnectInfo(client): ConnectInfo<SocketAddr>| async move { client.to_string() }),
+    );
+    let acceptor = ProxyAcceptor::new(listener, Duration::from_millis(500));
+    tokio::spawn(
+        axum::Server::builder(acceptor)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+    );
+    addr
+}
+
+/// Sends `header` followed by a request and returns the response, empty if the connection was
+/// closed without one.
+async fn request(addr: SocketAddr, header: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(header).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response).await;
+    response
+}
+
+#[tokio::test]
+async fn acceptor() {
+    let addr = serve().await;
+
+    let response = request(addr, b"PROXY TCP4 192.0.2.1 192.0.2.2 4321 80\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("192.0.2.1:4321"), "{}", response);
+
+    // without a client address the load balancer is the client
+    let response = request(addr, &v2(0x20, 0x00, &[])).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\r\n\r\n127.0.0.1:"), "{}", response);
+
+    assert_eq!(request(addr, b"").await, "");
+
+    // a client that never sends the header does not hold up others
+    let _silent = TcpStream::connect(addr).await.unwrap();
+    let response = request(addr, b"PROXY TCP4 192.0.2.3 192.0.2.2 4321 80\r\n").await;
+    assert!(response.ends_with("192.0.2.3:4321"), "{}", response);
+}
@@ -0,0 +1,263 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use dnsbl::rbldnsd::{Dataset, DatasetKind, LocalList};
+use dnsbl::{BlockStatus, Domain, ReturnCodes};
+
+fn local(kind: DatasetKind, data: &str) -> LocalList {
+    LocalList::new().dataset(Dataset::parse(kind, data).unwrap())
+}
+
+/// Returned addresses and message, `None` if not blocked.
+fn answer(status: BlockStatus) -> Option<(Vec<Ipv4Addr>, Option<String>)> {
+    match status {
+        BlockStatus::Blocked {
+            addresses, message, ..
+        } => Some((addresses, message)),
+        BlockStatus::NotBlocked => None,
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+fn listed(list: &LocalList, ip: &str) -> bool {
+    list.check_ip(ip.parse::<IpAddr>().unwrap()).is_blocked()
+}
+
+/// Query name of an IPv6 address relative to the list, in reversed nibbles.
+fn nibbles(ip: Ipv6Addr) -> String {
+    let hex = format!("{:032x}", u128::from(ip));
+    let nibbles: Vec<String> = hex.chars().rev().map(String::from).collect();
+    nibbles.join(".")
+}
+
+#[test]
+fn ip4_short_forms() {
+    let list = local(DatasetKind::Ip4Set, "10.1\n10.2.3\n10.3.4.5\n11\n");
+
+    assert!(listed(&list, "10.1.0.0"));
+    assert!(listed(&list, "10.1.255.255"));
+    assert!(!listed(&list, "10.0.255.255"));
+    assert!(listed(&list, "10.2.3.200"));
+    assert!(!listed(&list, "10.2.4.0"));
+    assert!(listed(&list, "10.3.4.5"));
+    assert!(!listed(&list, "10.3.4.6"));
+    assert!(listed(&list, "11.200.0.1"));
+}
+
+#[test]
+fn ip4_ranges() {
+    let list = local(
+        DatasetKind::Ip4Set,
+        "192.0.2.10-20\n198.51.100.250-198.51.101.5\n0.0.0.0-0.0.0.2\n",
+    );
+
+    assert!(!listed(&list, "192.0.2.9"));
+    for last in 10..=20 {
+        assert!(listed(&list, &format!("192.0.2.{}", last)), "{}", last);
+    }
+    assert!(!listed(&list, "192.0.2.21"));
+
+    assert!(!listed(&list, "198.51.100.249"));
+    assert!(listed(&list, "198.51.100.250"));
+    assert!(listed(&list, "198.51.100.255"));
+    assert!(listed(&list, "198.51.101.0"));
+    assert!(listed(&list, "198.51.101.5"));
+    assert!(!listed(&list, "198.51.101.6"));
+
+    assert!(listed(&list, "0.0.0.0"));
+    assert!(listed(&list, "0.0.0.2"));
+    assert!(!listed(&list, "0.0.0.3"));
+
+    assert!(Dataset::parse(DatasetKind::Ip4Set, "192.0.2.20-10").is_err());
+    assert!(Dataset::parse(DatasetKind::Ip4Set, "192.0.2.0/33").is_err());
+    assert!(Dataset::parse(DatasetKind::Ip4Set, "192.0.2.256").is_err());
+}
+
+#[test]
+fn full_range() {
+    let list = local(DatasetKind::Ip4Set, "0.0.0.0-255.255.255.255\n");
+    assert!(listed(&list, "0.0.0.0"));
+    assert!(listed(&list, "128.0.0.1"));
+    assert!(listed(&list, "255.255.255.255"));
+}
+
+#[test]
+fn exclusions() {
+    let list = local(
+        DatasetKind::Ip4Set,
+        "10.0.0.0/8\n!10.1.2.0/24\n10.1.2.7\n!10.5.0.0/16\n10.5.0.0/16\n",
+    );
+
+    assert!(listed(&list, "10.1.1.255"));
+    assert!(!listed(&list, "10.1.2.6"));
+    assert!(listed(&list, "10.1.2.7"));
+    assert!(listed(&list, "10.1.3.0"));
+    // an exclusion wins over an entry for the same block, whatever the order
+    assert!(!listed(&list, "10.5.1.1"));
+
+    let list = local(
+        DatasetKind::Ip6Trie,
+        "2001:db8::/32 :127.0.0.3:v6\n!2001:db8:1::/48\n",
+    );
+    assert!(listed(&list, "2001:db8::1"));
+    assert!(!listed(&list, "2001:db8:1::1"));
+    assert!(listed(&list, "2001:db8:2::1"));
+    assert!(!listed(&list, "2001:db9::1"));
+}
+
+#[test]
+fn ip4tset() {
+    let list = local(DatasetKind::Ip4TSet, ":127.0.0.3:Listed\n1.2.3.4\n");
+    assert_eq!(
+        answer(list.check_ip([1, 2, 3, 4])),
+        Some((vec![Ipv4Addr::new(127, 0, 0, 3)], Some("Listed".to_owned())))
+    );
+    assert!(!listed(&list, "1.2.3.5"));
+
+    assert!(Dataset::parse(DatasetKind::Ip4TSet, "1.2.3.0/24").is_err());
+    assert!(Dataset::parse(DatasetKind::Ip4TSet, "1.2.3").is_err());
+    assert!(Dataset::parse(DatasetKind::Ip4TSet, "1.2.3.4-5").is_err());
+}
+
+#[test]
+fn dnset() {
+    let list = local(
+        DatasetKind::DnSet,
+        ".example.com\n*.wild.test :127.0.0.3\nexact.test.\n!good.example.com\n",
+    );
+    let is_listed = |name: &str| list.check_domain(&Domain::new(name).unwrap()).is_blocked();
+
+    assert!(is_listed("example.com"));
+    assert!(is_listed("mail.example.com"));
+    assert!(is_listed("a.b.example.com"));
+    assert!(!is_listed("good.example.com"));
+    assert!(!is_listed("wild.test"));
+    assert!(is_listed("a.wild.test"));
+    assert!(is_listed("Exact.Test"));
+    assert!(!is_listed("a.exact.test"));
+    assert!(!is_listed("example.org"));
+}
+
+#[test]
+fn default_values() {
+    let list = local(
+        DatasetKind::Ip4Set,
+        "1.2.3.1\n:127.0.0.4:Default for $\n1.2.3.4\n1.2.3.5 :127.0.0.5:Own\n\
+         1.2.3.7 :127.0.0.7\n1.2.3.8 Text only\n:6:Changed\n1.2.3.6\n",
+    );
+    let value = |ip: [u8; 4]| answer(list.check_ip(ip)).unwrap();
+
+    assert_eq!(
+        value([1, 2, 3, 1]),
+        (vec![Ipv4Addr::new(127, 0, 0, 2)], None)
+    );
+    assert_eq!(
+        value([1, 2, 3, 4]),
+        (
+            vec![Ipv4Addr::new(127, 0, 0, 4)],
+            Some("Default for 1.2.3.4".to_owned())
+        )
+    );
+    assert_eq!(
+        value([1, 2, 3, 5]),
+        (vec![Ipv4Addr::new(127, 0, 0, 5)], Some("Own".to_owned()))
+    );
+    assert_eq!(
+        value([1, 2, 3, 7]),
+        (
+            vec![Ipv4Addr::new(127, 0, 0, 7)],
+            Some("Default for 1.2.3.7".to_owned())
+        )
+    );
+    assert_eq!(
+        value([1, 2, 3, 8]),
+        (
+            vec![Ipv4Addr::new(127, 0, 0, 4)],
+            Some("Text only".to_owned())
+        )
+    );
+    assert_eq!(
+        value([1, 2, 3, 6]),
+        (
+            vec![Ipv4Addr::new(127, 0, 0, 6)],
+            Some("Changed".to_owned())
+        )
+    );
+}
+
+#[test]
+fn substitution() {
+    let list = local(
+        DatasetKind::Ip4Set,
+        "$0 https://lookup.test/?\n$1 spam\n\
+         1.2.3.4 :2:Listed for $1, see $0$\n1.2.3.5 :2:Unset $9variable for $\n",
+    );
+
+    let (_, message) = answer(list.check_ip([1, 2, 3, 4])).unwrap();
+    assert_eq!(
+        message.as_deref(),
+        Some("Listed for spam, see https://lookup.test/?1.2.3.4")
+    );
+    let (_, message) = answer(list.check_ip([1, 2, 3, 5])).unwrap();
+    assert_eq!(message.as_deref(), Some("Unset variable for 1.2.3.5"));
+}
+
+#[test]
+fn generic() {
+    let dataset = Dataset::parse(
+        DatasetKind::Generic,
+        "$TTL 600\n@ 3600 A 127.0.0.9\n4.3.2.1 3600 A 127.0.0.2\n4.3.2.1 TXT \"listed $\"\n\
+         spam.example. a 127.0.0.3\nspam.example MX 10 mail.spam.example\n",
+    )
+    .unwrap();
+    assert_eq!(dataset.kind(), DatasetKind::Generic);
+    assert_eq!(dataset.ttl(), Some(600));
+    let list = LocalList::new().dataset(dataset);
+
+    assert_eq!(
+        answer(list.check_ip([1, 2, 3, 4])),
+        Some((
+            vec![Ipv4Addr::new(127, 0, 0, 2)],
+            Some("listed 1.2.3.4".to_owned())
+        ))
+    );
+    assert_eq!(
+        answer(list.check_domain(&Domain::new("spam.example").unwrap())),
+        Some((vec![Ipv4Addr::new(127, 0, 0, 3)], None))
+    );
+    assert!(!listed(&list, "1.2.3.5"));
+
+    assert!(Dataset::parse(DatasetKind::Generic, "$TTL soon").is_err());
+    assert!(Dataset::parse(DatasetKind::Generic, "4.3.2.1 A nowhere").is_err());
+    assert!(Dataset::parse(DatasetKind::Generic, "4.3.2.1").is_err());
+}
+
+#[test]
+fn query_names() {
+    let list = LocalList::new()
+        .dataset(Dataset::parse(DatasetKind::Ip4Set, "1.2.3.4\n").unwrap())
+        .dataset(Dataset::parse(DatasetKind::Ip6Trie, "2001:db8::/64 :127.0.0.3:v6\n").unwrap())
+        .dataset(Dataset::parse(DatasetKind::DnSet, "spam.example\n").unwrap())
+        .return_codes(ReturnCodes::new().code([127, 0, 0, 3], "IPv6"));
+
+    assert!(list.check_query("4.3.2.1").is_blocked());
+    assert!(!list.check_query("1.2.3.4").is_blocked());
+    assert!(list.check_query("spam.example").is_blocked());
+    assert!(!list.check_query("4.3.2").is_blocked());
+
+    let ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+    match list.check_query(&format!("{}.", nibbles(ip))) {
+        BlockStatus::Blocked {
+            addresses, reasons, ..
+        } => {
+            assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 3)]);
+            assert!(reasons.contains("IPv6"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+    let outside: Ipv6Addr = "2001:db8:0:1::1".parse().unwrap();
+    assert!(!list.check_query(&nibbles(outside)).is_blocked());
+
+    // a label with more than one nibble makes it a domain
+    let name = nibbles(ip).replacen("1.", "10.", 1);
+    assert!(!list.check_query(&name).is_blocked());
+}
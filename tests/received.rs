@@ -0,0 +1,75 @@
+use std::net::IpAddr;
+
+use dnsbl::received::{hops, Hop};
+
+fn ip(ip: &str) -> Option<IpAddr> {
+    Some(ip.parse().unwrap())
+}
+
+#[test]
+fn postfix() {
+    let hop = Hop::parse(
+        0,
+        "from helo.example.com (mail.example.com [192.0.2.1])\r\n\tby mx.example.net (Postfix) with ESMTPS id 4F1; Mon, 1 Jan 2024 00:00:00 +0000",
+    );
+    assert_eq!(hop.helo.as_deref(), Some("helo.example.com"));
+    assert_eq!(hop.ip, ip("192.0.2.1"));
+    assert_eq!(hop.by.as_deref(), Some("mx.example.net"));
+}
+
+#[test]
+fn postfix_address_literal_helo() {
+    let hop = Hop::parse(
+        0,
+        "from [198.51.100.7] (unknown [192.0.2.1]) by mx.example.net (Postfix) with SMTP",
+    );
+    assert_eq!(hop.ip, ip("192.0.2.1"));
+}
+
+#[test]
+fn exim() {
+    let hop = Hop::parse(
+        0,
+        "from mail.example.com ([2001:db8::1] helo=helo.example.com) by mx.example.net with esmtps (Exim 4.96)",
+    );
+    assert_eq!(hop.helo.as_deref(), Some("helo.example.com"));
+    assert_eq!(hop.ip, ip("2001:db8::1"));
+}
+
+#[test]
+fn qmail() {
+    let hop = Hop::parse(
+        0,
+        "from unknown (HELO 198.51.100.7) (192.0.2.1) by mx.example.net with SMTP",
+    );
+    assert_eq!(hop.helo.as_deref(), Some("198.51.100.7"));
+    assert_eq!(hop.ip, ip("192.0.2.1"));
+}
+
+#[test]
+fn exchange() {
+    let hop = Hop::parse(
+        0,
+        "from EX01.corp.example (10.0.0.1) by EX02.corp.example (10.0.0.2) with Microsoft SMTP Server",
+    );
+    assert_eq!(hop.ip, ip("10.0.0.1"));
+    assert_eq!(hop.by.as_deref(), Some("EX02.corp.example"));
+}
+
+#[test]
+fn message() {
+    let message = "Received: from mx.example.net (mx.example.net [203.0.113.5])\r\n\
+                   \tby inbox.example.net with ESMTP\r\n\
+                   Subject: test\r\n\
+                   Received: from helo.example.com (mail.example.com [192.0.2.1])\r\n\
+                   \tby mx.example.net with ESMTP\r\n\
+                   Received: by mail.example.com with local\r\n\
+                   \r\n\
+                   Received: from body.example (body.example [198.51.100.1])\r\n";
+    let hops = hops(message);
+    assert_eq!(hops.len(), 3);
+    assert_eq!(hops[0].ip, ip("203.0.113.5"));
+    assert_eq!(hops[1].ip, ip("192.0.2.1"));
+    assert_eq!(hops[2].index, 2);
+    assert_eq!(hops[2].ip, None);
+}
@@ -0,0 +1,112 @@
+use std::net::Ipv4Addr;
+
+use dnsbl::score::{AddressFilter, ScoreEntry};
+use dnsbl::BlockList;
+
+fn filter(s: &str) -> AddressFilter {
+    s.parse().unwrap()
+}
+
+#[test]
+fn filter_round_trip() {
+    for s in [
+        "127.0.0.2",
+        "127.0.0.[2..11]",
+        "127.0.[0..255].[1..3]",
+        "127.[0;2..4].0.1",
+    ]
+    .iter()
+    {
+        assert_eq!(filter(s).to_string(), *s);
+    }
+    assert_eq!(filter("127.0.0.[2..2]").to_string(), "127.0.0.2");
+}
+
+#[test]
+fn filter_matches() {
+    let range = filter("127.0.0.[2..11]");
+    assert!(range.matches(Ipv4Addr::new(127, 0, 0, 2)));
+    assert!(range.matches(Ipv4Addr::new(127, 0, 0, 11)));
+    assert!(!range.matches(Ipv4Addr::new(127, 0, 0, 12)));
+    assert!(!range.matches(Ipv4Addr::new(127, 0, 1, 2)));
+
+    let alternatives = filter("127.0.0.[1;3]");
+    assert!(alternatives.matches(Ipv4Addr::new(127, 0, 0, 1)));
+    assert!(!alternatives.matches(Ipv4Addr::new(127, 0, 0, 2)));
+    assert!(alternatives.matches(Ipv4Addr::new(127, 0, 0, 3)));
+}
+
+#[test]
+fn filter_invalid() {
+    for s in [
+        "127.0.0",
+        "127.0.0.1.1",
+        "127.0.0.256",
+        "127.0.0.2x",
+        "127.0.0.[5..2]",
+        "127.0.0.[2..3",
+        "127.0.0.[]",
+        "127..0.1",
+    ]
+    .iter()
+    {
+        assert!(s.parse::<AddressFilter>().is_err(), "{} parsed", s);
+    }
+}
+
+#[test]
+fn entry() {
+    let entry: ScoreEntry = "zen.spamhaus.org=127.0.0.[2..11]*3".parse().unwrap();
+    assert_eq!(entry.list, "zen.spamhaus.org".parse::<BlockList>().unwrap());
+    assert_eq!(entry.filter, Some(filter("127.0.0.[2..11]")));
+    assert_eq!(entry.weight, 3);
+
+    let entry: ScoreEntry = "list.dnswl.org=127.0.[0..255].[1..3]*-5".parse().unwrap();
+    assert_eq!(entry.weight, -5);
+    assert_eq!(entry.to_string(), "list.dnswl.org=127.0.[0..255].[1..3]*-5");
+
+    let entry: ScoreEntry = "bl.spamcop.net".parse().unwrap();
+    assert_eq!(entry.filter, None);
+    assert_eq!(entry.weight, 1);
+    assert_eq!(entry.to_string(), "bl.spamcop.net");
+
+    assert!("bl.spamcop.net*x".parse::<ScoreEntry>().is_err());
+    assert!("bl.spamcop.net=127.0.0".parse::<ScoreEntry>().is_err());
+}
+
+#[test]
+fn parse_all() {
+    let entries = ScoreEntry::parse_all("a.test*2, b.test\n  c.test=127.0.0.[2;4]*-1,,").unwrap();
+    let entries: Vec<String> = entries.iter().map(ScoreEntry::to_string).collect();
+    assert_eq!(entries, ["a.test*2", "b.test", "c.test=127.0.0.[2;4]*-1"]);
+
+    assert!(ScoreEntry::parse_all("a.test b.test*").is_err());
+}
+
+#[cfg(feature = "test-server")]
+#[tokio::test]
+async fn score() {
+    use dnsbl::test_server::{TestServer, TestZone};
+
+    let zone = TestZone::new(&"bl.test".parse().unwrap())
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], None)
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 10], None);
+    let allow =
+        TestZone::new(&"allow.test".parse().unwrap()).list_ip([1, 2, 3, 4], [127, 0, 0, 2], None);
+    let server = TestServer::start(vec![zone, allow]).await.unwrap();
+    let dnsbl = server.builder().build().unwrap();
+
+    let entries = ScoreEntry::parse_all(
+        "bl.test=127.0.0.[2..3]*3 bl.test=127.0.0.10*2 bl.test=127.0.0.4*5 allow.test*-1",
+    )
+    .unwrap();
+    let score = dnsbl.score(&entries, Ipv4Addr::new(1, 2, 3, 4)).await;
+    let scores: Vec<i32> = score.breakdown.iter().map(|item| item.score).collect();
+    assert_eq!(scores, [3, 2, 0, -1]);
+    assert_eq!(score.total, 4);
+    assert!(score.reaches(4));
+    assert!(!score.reaches(5));
+
+    let score = dnsbl.score(&entries, Ipv4Addr::new(1, 2, 3, 5)).await;
+    assert_eq!(score.total, 0);
+}
@@ -0,0 +1,244 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use common::{domain, list, resolver, serve, silent};
+use dnsbl::proxy::ProxyZone;
+use dnsbl::rbldnsd::{Dataset, DatasetKind, LocalList};
+use dnsbl::server::Server;
+use dnsbl::{BlockStatus, CacheConfig, Domain, ErrorKind, DNSBL};
+
+/// A resolver without any cached answers from earlier queries.
+fn dnsbl(addr: SocketAddr) -> DNSBL {
+    resolver(addr).build().unwrap()
+}
+
+fn local(datasets: &[(DatasetKind, &str)]) -> LocalList {
+    datasets
+        .iter()
+        .fold(LocalList::new(), |list, &(kind, data)| {
+            list.dataset(Dataset::parse(kind, data).unwrap())
+        })
+}
+
+fn write(path: &Path, data: &str, modified: SystemTime) {
+    fs::write(path, data).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dnsbl-server-{}-{}", std::process::id(), name))
+}
+
+#[tokio::test]
+async fn answers() {
+    let server = Server::new();
+    server.set_zone(
+        &"bl.test".parse().unwrap(),
+        local(&[
+            (
+                DatasetKind::Ip4Set,
+                "1.2.3.4 :127.0.0.3:Listed $\n1.2.3.6\n",
+            ),
+            (DatasetKind::Ip6Trie, "2a00:1450::/32 :127.0.0.4:IPv6 $\n"),
+            (DatasetKind::DnSet, "spam.example :127.0.1.2:Spam domain\n"),
+        ]),
+    );
+    let dnsbl = dnsbl(serve(&server).await);
+    let bl = list("bl.test");
+
+    match dnsbl.check_ip(&bl, Ipv4Addr::new(1, 2, 3, 4)).await {
+        BlockStatus::Blocked {
+            addresses, message, ..
+        } => {
+            assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 3)]);
+            assert_eq!(message.as_deref(), Some("Listed 1.2.3.4"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+    match dnsbl.check_ip(&bl, Ipv4Addr::new(1, 2, 3, 6)).await {
+        BlockStatus::Blocked {
+            addresses, message, ..
+        } => {
+            assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 2)]);
+            assert_eq!(message, None);
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+    match dnsbl.check_domain(&bl, &domain("spam.example")).await {
+        BlockStatus::Blocked {
+            addresses, message, ..
+        } => {
+            assert_eq!(addresses, [Ipv4Addr::new(127, 0, 1, 2)]);
+            assert_eq!(message.as_deref(), Some("Spam domain"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    assert!(matches!(
+        dnsbl.check_ip(&bl, Ipv4Addr::new(1, 2, 3, 5)).await,
+        BlockStatus::NotBlocked
+    ));
+    assert!(matches!(
+        dnsbl.check_domain(&bl, &domain("ham.example")).await,
+        BlockStatus::NotBlocked
+    ));
+}
+
+#[tokio::test]
+async fn ipv6() {
+    let server = Server::new();
+    server.set_zone(
+        &"bl.test".parse().unwrap(),
+        local(&[(DatasetKind::Ip6Trie, "2a00:1450::/32 :127.0.0.4:IPv6 $\n")]),
+    );
+    let dnsbl = dnsbl(serve(&server).await);
+
+    let ip: IpAddr = "2a00:1450::1".parse().unwrap();
+    match dnsbl.check_ip(&list("bl.test"), ip).await {
+        BlockStatus::Blocked {
+            addresses, message, ..
+        } => {
+            assert_eq!(addresses, [Ipv4Addr::new(127, 0, 0, 4)]);
+            assert_eq!(message.as_deref(), Some("IPv6 2a00:1450::1"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+    let ip: IpAddr = "2a00:1451::1".parse().unwrap();
+    assert!(matches!(
+        dnsbl.check_ip(&list("bl.test"), ip).await,
+        BlockStatus::NotBlocked
+    ));
+}
+
+#[tokio::test]
+async fn negative_answers() {
+    let server = Server::new();
+    server.set_zone(
+        &"bl.test".parse().unwrap(),
+        local(&[(DatasetKind::Ip4Set, "$TTL 1\n1.2.3.4\n")]),
+    );
+    let addr = serve(&server).await;
+    let dnsbl = resolver(addr)
+        .cache(CacheConfig {
+            min_ttl: Duration::from_secs(0),
+            negative_ttl: Duration::from_secs(3600),
+            ..CacheConfig::default()
+        })
+        .build()
+        .unwrap();
+
+    // NXDOMAIN answers carry the zone's SOA, whose TTL of 1s limits negative caching
+    let bl = list("bl.test");
+    assert!(matches!(
+        dnsbl.check_ip(&bl, Ipv4Addr::new(1, 2, 3, 5)).await,
+        BlockStatus::NotBlocked
+    ));
+    assert_eq!(dnsbl.cache_stats().unwrap().entries, 1);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    dnsbl.check_ip(&bl, Ipv4Addr::new(1, 2, 3, 5)).await;
+    let stats = dnsbl.cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (0, 2));
+
+    match dnsbl
+        .check_ip(&list("other.test"), Ipv4Addr::new(1, 2, 3, 4))
+        .await
+    {
+        BlockStatus::Error { kind, .. } => assert_eq!(kind, ErrorKind::Refused),
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn reload() {
+    let path = temp_file("reload");
+    let modified = SystemTime::now() - Duration::from_secs(60);
+    write(&path, "1.2.3.4\n", modified);
+
+    let origin: Domain = "bl.test".parse().unwrap();
+    let server = Server::new();
+    server
+        .load_zone(&origin, vec![(DatasetKind::Ip4Set, &path)])
+        .unwrap();
+    let addr = serve(&server).await;
+    let listed = |last: u8| async move {
+        dnsbl(addr)
+            .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, last))
+            .await
+            .is_blocked()
+    };
+
+    assert!(listed(4).await);
+    assert!(!listed(5).await);
+    assert!(server.reload().is_empty());
+    assert!(listed(4).await);
+
+    write(&path, "1.2.3.5\n", modified + Duration::from_secs(10));
+    assert!(server.reload().is_empty());
+    assert!(!listed(4).await);
+    assert!(listed(5).await);
+
+    // a broken file keeps the previous data
+    write(&path, "1.2.3.x\n", modified + Duration::from_secs(20));
+    let errors = server.reload();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, origin);
+    assert!(!listed(4).await);
+    assert!(listed(5).await);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn max_pending() {
+    // a proxy zone whose upstream never answers holds the only permit
+    let silent = silent();
+    let upstream = silent.local_addr().unwrap();
+    let upstream = resolver(upstream)
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+    let server = Server::new().max_pending(1);
+    server.set_proxy_zone(
+        &"proxy.test".parse().unwrap(),
+        ProxyZone::new(Arc::new(upstream), vec![list("bl.test")]),
+        60,
+    );
+    server.set_zone(
+        &"bl.test".parse().unwrap(),
+        local(&[(DatasetKind::Ip4Set, "1.2.3.4\n")]),
+    );
+    let addr = serve(&server).await;
+
+    let pending = tokio::spawn(async move {
+        dnsbl(addr)
+            .check_ip(&list("proxy.test"), Ipv4Addr::new(1, 2, 3, 4))
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    match dnsbl(addr)
+        .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, 4))
+        .await
+    {
+        BlockStatus::Error { kind, .. } => assert_eq!(kind, ErrorKind::Refused),
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    // the permit is returned once the upstream timed out
+    assert!(matches!(pending.await.unwrap(), BlockStatus::Error { .. }));
+    let status = dnsbl(addr)
+        .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, 4))
+        .await;
+    assert!(status.is_blocked());
+}
@@ -0,0 +1,346 @@
+#![cfg(feature = "test-server")]
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use common::{broken, domain as domain_name, list, resolver};
+use dnsbl::test_server::{TestServer, TestZone};
+use dnsbl::{
+    AddressClass, AddressPolicy, BlockStatus, CacheConfig, ErrorKind, HashDescriptor, Health,
+    QueryKind, ReturnCodes,
+};
+
+async fn server() -> TestServer {
+    let zone = TestZone::new(&list("bl.test"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 2], Some("spam source"))
+        .list_ip([1, 2, 3, 4], [127, 0, 0, 10], None)
+        .list_ip([1, 2, 3, 6], [127, 255, 255, 254], None)
+        .list_ip(
+            "2a00:1450::1".parse::<IpAddr>().unwrap(),
+            [127, 0, 0, 3],
+            None,
+        )
+        .list_ip([127, 0, 0, 2], [127, 0, 0, 2], None)
+        .list_ip([10, 0, 0, 1], [127, 0, 0, 2], None)
+        .list_domain(
+            &domain_name("spam.example"),
+            [127, 0, 1, 2],
+            Some("spam domain"),
+        )
+        .list_domain(&domain_name("spam.co.uk"), [127, 0, 1, 2], None)
+        .list_domain(&domain_name("test"), [127, 0, 1, 2], None);
+    let failing = broken("broken.test");
+
+    TestServer::start(vec![zone, failing]).await.unwrap()
+}
+
+#[tokio::test]
+async fn ipv4_listed() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+    let list = list("bl.test").return_codes(ReturnCodes::spamhaus_zen());
+
+    match dnsbl.check_ip(&list, Ipv4Addr::new(1, 2, 3, 4)).await {
+        BlockStatus::Blocked {
+            mut addresses,
+            reasons,
+            message,
+        } => {
+            addresses.sort();
+            assert_eq!(
+                addresses,
+                vec![Ipv4Addr::new(127, 0, 0, 2), Ipv4Addr::new(127, 0, 0, 10)]
+            );
+            assert_eq!(reasons.into_iter().collect::<Vec<_>>(), vec!["PBL", "SBL"]);
+            assert_eq!(message.as_deref(), Some("spam source"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn ipv4_not_listed() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, 5))
+        .await;
+    assert_eq!(status, BlockStatus::NotBlocked);
+}
+
+#[tokio::test]
+async fn refused_query() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+    let ip = Ipv4Addr::new(1, 2, 3, 6);
+
+    let zen = list("bl.test").return_codes(ReturnCodes::spamhaus_zen());
+    match dnsbl.check_ip(&zen, ip).await {
+        BlockStatus::Error { kind, source } => {
+            assert_eq!(kind, ErrorKind::Refused);
+            assert!(source.to_string().contains("public resolver"));
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    // Without error codes the answer is taken as a listing.
+    assert!(dnsbl.check_ip(&list("bl.test"), ip).await.is_blocked());
+}
+
+#[tokio::test]
+async fn ipv6_listed() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+
+    let ip: IpAddr = "2a00:1450::1".parse().unwrap();
+    assert!(dnsbl.check_ip(&list("bl.test"), ip).await.is_blocked());
+
+    let ip: IpAddr = "2a00:1450::2".parse().unwrap();
+    assert_eq!(
+        dnsbl.check_ip(&list("bl.test"), ip).await,
+        BlockStatus::NotBlocked
+    );
+}
+
+#[tokio::test]
+async fn address_policy() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(10, 0, 0, 1))
+        .await;
+    assert_eq!(status, BlockStatus::NotApplicable);
+    let ip: IpAddr = "::ffff:1.2.3.4".parse().unwrap();
+    assert!(dnsbl.check_ip(&list("bl.test"), ip).await.is_blocked());
+    // test points are queried unless turned off
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(127, 0, 0, 2))
+        .await;
+    assert!(status.is_blocked());
+    // `http://123/` is taken as 0.0.0.123
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(0, 0, 0, 123))
+        .await;
+    assert_eq!(status, BlockStatus::NotApplicable);
+
+    let dnsbl = server
+        .builder()
+        .address_policy(AddressPolicy::default().allow(AddressClass::Private))
+        .build()
+        .unwrap();
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(10, 0, 0, 1))
+        .await;
+    assert!(status.is_blocked());
+
+    let dnsbl = server
+        .builder()
+        .address_policy(AddressPolicy::default().test_points(false))
+        .build()
+        .unwrap();
+    let status = dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(127, 0, 0, 2))
+        .await;
+    assert_eq!(status, BlockStatus::NotApplicable);
+    // health checks bypass the policy
+    assert_eq!(
+        dnsbl.health_check(&list("bl.test")).await.ipv4,
+        Health::Alive
+    );
+}
+
+#[tokio::test]
+async fn domain() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+
+    match dnsbl
+        .check_domain(&list("bl.test"), &domain_name("spam.example"))
+        .await
+    {
+        BlockStatus::Blocked { message, .. } => {
+            assert_eq!(message.as_deref(), Some("spam domain"))
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    let status = dnsbl
+        .check_domain(&list("bl.test"), &domain_name("ham.example"))
+        .await;
+    assert_eq!(status, BlockStatus::NotBlocked);
+}
+
+#[tokio::test]
+async fn email_sender() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+    let lists = [list("bl.test")];
+
+    let results = dnsbl
+        .check_email_sender(&lists, "Spammer <info@Mail.SPAM.co.uk>")
+        .await
+        .unwrap();
+    assert!(results.is_blocked());
+    assert_eq!(
+        results.iter().next().unwrap().query,
+        domain_name("spam.co.uk.bl.test")
+    );
+
+    let results = dnsbl
+        .check_email_sender(&lists, "info@ham.co.uk")
+        .await
+        .unwrap();
+    assert!(!results.is_blocked());
+
+    assert!(dnsbl.check_email_sender(&lists, "info").await.is_err());
+}
+
+#[tokio::test]
+async fn hash() {
+    let descriptor = HashDescriptor::spamhaus_hbl_email();
+    let digest = descriptor.digest(b"spammer@example.com");
+    let zone = TestZone::new(&list("hbl.test")).list_domain(
+        &domain_name(&format!("{}._email", digest)),
+        [127, 0, 3, 2],
+        None,
+    );
+    let server = TestServer::start(vec![zone]).await.unwrap();
+    let dnsbl = server.builder().build().unwrap();
+    let hbl = list("hbl.test").hash_descriptor(descriptor);
+
+    let status = dnsbl.check_hash(&hbl, " Spammer@Example.com").await;
+    assert!(status.is_blocked());
+
+    let status = dnsbl.check_hash(&hbl, "other@example.com").await;
+    assert_eq!(status, BlockStatus::NotBlocked);
+
+    // without the descriptor the raw value is hashed
+    let status = dnsbl
+        .check_hash(&list("hbl.test"), "spammer@example.com")
+        .await;
+    assert_eq!(status, BlockStatus::NotBlocked);
+}
+
+#[tokio::test]
+async fn server_failure() {
+    let server = server().await;
+    let dnsbl = resolver(server.addr()).build().unwrap();
+
+    let status = dnsbl
+        .check_ip(&list("broken.test"), Ipv4Addr::new(1, 2, 3, 4))
+        .await;
+    assert!(matches!(status, BlockStatus::Error { .. }));
+}
+
+#[tokio::test]
+async fn multiple_lists() {
+    let server = server().await;
+    let dnsbl = resolver(server.addr()).concurrency(2).build().unwrap();
+    let lists = [list("bl.test"), list("broken.test"), list("other.test")];
+
+    let results = dnsbl.check_ip_all(&lists, Ipv4Addr::new(1, 2, 3, 4)).await;
+
+    let listed: Vec<_> = results.iter().map(|result| result.list.clone()).collect();
+    assert_eq!(listed, lists);
+    assert!(results.is_blocked());
+    assert!(results.get(&lists[0]).unwrap().status.is_blocked());
+    assert!(matches!(
+        results.get(&lists[1]).unwrap().status,
+        BlockStatus::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn cache() {
+    let server = server().await;
+    let dnsbl = server
+        .builder()
+        .cache(CacheConfig::default())
+        .build()
+        .unwrap();
+
+    for _ in 0..3 {
+        let status = dnsbl
+            .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, 4))
+            .await;
+        assert!(status.is_blocked());
+    }
+
+    let stats = dnsbl.cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+}
+
+#[tokio::test]
+async fn cache_capacity() {
+    let server = server().await;
+    let dnsbl = server
+        .builder()
+        .cache(CacheConfig {
+            capacity: 2,
+            ..CacheConfig::default()
+        })
+        .build()
+        .unwrap();
+
+    for last in 1..=5 {
+        dnsbl
+            .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, last))
+            .await;
+    }
+    assert_eq!(dnsbl.cache_stats().unwrap().entries, 2);
+
+    // The most recent answers are kept.
+    dnsbl
+        .check_ip(&list("bl.test"), Ipv4Addr::new(1, 2, 3, 5))
+        .await;
+    assert_eq!(dnsbl.cache_stats().unwrap().hits, 1);
+}
+
+#[tokio::test]
+async fn health_check() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+
+    let report = dnsbl.health_check(&list("bl.test")).await;
+    assert_eq!(report.ipv4, Health::Alive);
+    assert_eq!(report.ipv6, Health::Dead);
+    assert_eq!(report.domain, Health::Alive);
+    assert!(report.is_alive());
+}
+
+#[tokio::test]
+async fn query_kinds() {
+    let server = server().await;
+    let dnsbl = server.builder().build().unwrap();
+    let ipv4 = list("bl.test").kinds(&[QueryKind::Ipv4]);
+
+    assert!(dnsbl
+        .check_ip(&ipv4, Ipv4Addr::new(1, 2, 3, 4))
+        .await
+        .is_blocked());
+    let ip = "2a00:1450::1".parse::<IpAddr>().unwrap();
+    assert_eq!(dnsbl.check_ip(&ipv4, ip).await, BlockStatus::NotApplicable);
+    assert_eq!(
+        dnsbl
+            .check_domain(&ipv4, &domain_name("spam.example"))
+            .await,
+        BlockStatus::NotApplicable
+    );
+
+    let results = dnsbl
+        .check_ip_all(&[ipv4.clone(), list("bl.test")], ip)
+        .await;
+    assert_eq!(
+        results.get(&ipv4).unwrap().status,
+        BlockStatus::NotApplicable
+    );
+    assert!(results.is_blocked());
+
+    let report = dnsbl.health_check(&ipv4).await;
+    assert_eq!(report.ipv4, Health::Alive);
+    assert_eq!(report.ipv6, Health::Unsupported);
+    assert_eq!(report.domain, Health::Unsupported);
+}